@@ -6,16 +6,12 @@ pub struct Assets {
     pub cursor: Texture,
     pub base_tile: Texture,
     pub farm_tile: Texture,
-    pub wheat_seed: Texture,
-    pub wheat_item: Texture,
-    pub wheat_0: Texture,
-    pub wheat_1: Texture,
-    pub wheat_2: Texture,
-    pub wheat_3: Texture,
+    pub ghost_tile: Texture,
+    pub ghost_tile_locked: Texture,
     pub pole: Texture,
-    pub pole_item: Texture,
-    pub wood_item: Texture,
-    pub sapling_item: Texture,
+    pub collector: Texture,
+    pub slot: Texture,
+    pub slot_selected: Texture,
 }
 
 impl Assets {
@@ -26,16 +22,12 @@ impl Assets {
             cursor: Texture::load("assets/misc/cursor.png")?,
             base_tile: Texture::load("assets/tiles/base_tile.png")?,
             farm_tile: Texture::load("assets/tiles/farm_tile.png")?,
-            wheat_seed: Texture::load("assets/items/wheat_seed.png")?,
-            wheat_item: Texture::load("assets/items/wheat_item.png")?,
-            wheat_0: Texture::load("assets/plants/wheat_0.png")?,
-            wheat_1: Texture::load("assets/plants/wheat_1.png")?,
-            wheat_2: Texture::load("assets/plants/wheat_2.png")?,
-            wheat_3: Texture::load("assets/plants/wheat_3.png")?,
+            ghost_tile: Texture::load("assets/tiles/ghost_tile.png")?,
+            ghost_tile_locked: Texture::load("assets/tiles/ghost_tile_locked.png")?,
             pole: Texture::load("assets/structures/pole.png")?,
-            pole_item: Texture::load("assets/items/pole_item.png")?,
-            wood_item: Texture::load("assets/items/wood_item.png")?,
-            sapling_item: Texture::load("assets/items/sapling_item.png")?,
+            collector: Texture::load("assets/structures/collector.png")?,
+            slot: Texture::load("assets/ui/slot.png")?,
+            slot_selected: Texture::load("assets/ui/slot_selected.png")?,
         })
     }
 }
@@ -4,8 +4,10 @@ use kira::{
         SoundClip,
     },
     manager::AudioManager,
+    instance::InstanceSettings,
     mixer::SubTrackHandle,
     sound::{handle::SoundHandle, SoundSettings},
+    Value,
 };
 
 pub struct Audio {
@@ -43,4 +45,15 @@ impl Audio {
             music_track,
         })
     }
+
+    #[inline]
+    pub fn play_hit(&mut self) {
+        let mut settings = InstanceSettings::new();
+
+        settings.volume = Value::Fixed(0.3);
+
+        if let Err(err) = self.hit_arrangement.play(settings) {
+            log::error!("failed to play hit sound: {}", err);
+        }
+    }
 }
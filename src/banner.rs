@@ -0,0 +1,121 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use ike::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::species::color;
+
+// design painted on the cloth of a pole, picked by the player before placing it
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Banner {
+    pub pattern: String,
+    // copied from the pattern so placed banners keep their look when patterns change
+    #[serde(default)]
+    pub rows: Vec<String>,
+    pub colors: [[u8; 3]; 2],
+}
+
+impl Default for Banner {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            pattern: String::from("plain"),
+            rows: Vec::new(),
+            colors: [[247, 16, 16], [250, 250, 250]],
+        }
+    }
+}
+
+impl Banner {
+    // color of the banner at `uv`, the top left is 0, 0 and the pole is on the left,
+    // `#` in the pattern gets the first color and anything else the second
+    #[inline]
+    pub fn color_at(&self, uv: Vec2) -> Color {
+        let height = self.rows.len();
+
+        if height == 0 {
+            return color(self.colors[0]);
+        }
+
+        let y = ((uv.y * height as f32) as usize).min(height - 1);
+        let row = &self.rows[y];
+
+        let width = row.chars().count().max(1);
+        let x = ((uv.x * width as f32) as usize).min(width - 1);
+
+        match row.chars().nth(x) {
+            Some('#') | None => color(self.colors[0]),
+            Some(_) => color(self.colors[1]),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+pub struct PatternDef {
+    // one string per row from the top
+    pub rows: Vec<String>,
+}
+
+impl PatternDef {
+    #[inline]
+    pub fn load(id: &str, path: &Path) -> ike::anyhow::Result<Self> {
+        let def: PatternDef = toml::from_str(&fs::read_to_string(path)?)?;
+
+        if def.rows.iter().any(|row| row.is_empty()) {
+            ike::anyhow::bail!("pattern '{}' has an empty row", id);
+        }
+
+        Ok(def)
+    }
+}
+
+// kept sorted by id so cycling through them always goes in the same order
+#[derive(Default)]
+pub struct Patterns {
+    pub patterns: BTreeMap<String, PatternDef>,
+}
+
+impl Patterns {
+    // loads every `*.toml` file in `path`, the file name is used as the pattern id
+    #[inline]
+    pub fn load(path: impl AsRef<Path>) -> ike::anyhow::Result<Self> {
+        let mut patterns = Self::default();
+
+        for entry in fs::read_dir(path)? {
+            let path = entry?.path();
+
+            if path.extension().map_or(true, |ext| ext != "toml") {
+                continue;
+            }
+
+            let id = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(id) => id.to_string(),
+                None => continue,
+            };
+
+            let def = PatternDef::load(&id, &path)
+                .map_err(|err| err.context(format!("failed to load pattern '{}'", id)))?;
+
+            patterns.patterns.insert(id, def);
+        }
+
+        Ok(patterns)
+    }
+
+    // the pattern after `id`, wrapping around to the first
+    #[inline]
+    pub fn next(&self, id: &str) -> Option<(&String, &PatternDef)> {
+        self.patterns
+            .range::<str, _>((std::ops::Bound::Excluded(id), std::ops::Bound::Unbounded))
+            .next()
+            .or_else(|| self.patterns.iter().next())
+    }
+}
+
+// a change to the banner picked by the player this step
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum BannerChange {
+    Pattern,
+    // cycles one of the two colors through the palette
+    Color(usize),
+}
@@ -26,6 +26,14 @@ var depth: texture_depth_2d;
 [[group(0), binding(2)]]
 var sampler: sampler;
 
+[[block]]
+struct Sky {
+	color: vec4<f32>;
+};
+
+[[group(0), binding(3)]]
+var<uniform> sky: Sky;
+
 struct FragmentOutput {
 	[[location(0)]] color: vec4<f32>;
 	[[builtin(frag_depth)]] depth: f32;
@@ -35,7 +43,10 @@ struct FragmentOutput {
 fn main(in: VertexOutput) -> FragmentOutput {
 	var out: FragmentOutput;
 	
-	out.color = textureSample(texture, sampler, in.uv);
+	let color = textureSample(texture, sampler, in.uv);
+
+	// the sky fills everything the 3d pass didn't draw over
+	out.color = vec4<f32>(mix(sky.color.rgb, color.rgb, color.a), 1.0);
 	out.depth = textureSample(depth, sampler, in.uv);
 
 	return out;
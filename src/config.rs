@@ -1,22 +1,55 @@
-use ike::prelude::*;
+use std::{
+    fs::{self, read_to_string},
+    path::Path,
+};
+
+use ike::{anyhow::ensure, prelude::*};
 use serde::{Deserialize, Serialize};
 
+use crate::{inventory::HOTBAR_SIZE, item::ItemType};
+
+pub const CONFIG_PATH: &str = "./config.toml";
+
+// bump when keys are renamed or moved and add a step to `migrate`, files
+// without a version are from before versioning and count as version 1
+pub const CONFIG_VERSION: u32 = 3;
+
+// written to `CONFIG_PATH` when it doesn't exist
+const DEFAULT_CONFIG: &str = include_str!("default_config.toml");
+
+// limits of the 3d pass texture height
+const D3_SCALE_MIN: u32 = 16;
+const D3_SCALE_MAX: u32 = 4096;
+
 #[derive(Serialize, Deserialize)]
-#[serde(rename_all = "kebab-case")]
+#[serde(default, rename_all = "kebab-case")]
 pub struct Controls {
     pub up: Key,
     pub down: Key,
     pub left: Key,
     pub right: Key,
     pub toggle_fullscreen: Key,
+    pub export_mesh: Key,
+    pub banner_pattern: Key,
+    pub banner_color: Key,
+    pub banner_accent: Key,
     pub primary: MouseButton,
     pub secondary: MouseButton,
     pub camera_speed: f32,
+    pub zoom_min: f32,
+    pub zoom_max: f32,
+    pub zoom_speed: f32,
+    pub zoom_smoothing: f32,
     pub item_offset: bool,
+    pub hotbar: Vec<Key>,
+    pub auto_pickup: bool,
+    pub pickup_radius: f32,
+    pub collector_radius: f32,
+    pub pickup_speed: f32,
 }
 
-#[derive(Serialize, Deserialize)]
-#[serde(rename_all = "kebab-case")]
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
 pub struct Window {
     pub cursor_grab: bool,
     pub maximized_default: bool,
@@ -24,39 +57,708 @@ pub struct Window {
 }
 
 #[derive(Serialize, Deserialize)]
-#[serde(rename_all = "kebab-case")]
+#[serde(default, rename_all = "kebab-case")]
 pub struct Graphics {
     pub custom_cursor: bool,
     pub always_show_stack_size: bool,
     pub d3_scale: u32,
-    pub instance_cloth: bool,
 }
 
 #[derive(Serialize, Deserialize)]
-#[serde(rename_all = "kebab-case")]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Cloth {
+    pub rate: f32,
+    pub iterations: u32,
+    pub stiffness: f32,
+    pub shear_stiffness: f32,
+    pub bend_stiffness: f32,
+    pub drag: f32,
+    pub damping: f32,
+    pub tear_stretch: f32,
+    pub grab_radius: f32,
+    pub push_radius: f32,
+    pub push_strength: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Wind {
+    pub direction: [f32; 2],
+    pub strength: f32,
+    pub gust_strength: f32,
+    pub gust_frequency: f32,
+    pub gust_size: f32,
+    pub turbulence: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Day {
+    pub length: f32,
+    pub start: f32,
+    pub day_sky: [u8; 3],
+    pub dusk_sky: [u8; 3],
+    pub night_sky: [u8; 3],
+    pub dusk_shade: [u8; 3],
+    pub night_shade: [u8; 3],
+    pub moonlight: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
 pub struct Tile {
     pub grass_growth_time: f32,
+    pub grass_drop: ItemType,
+    pub grass_drop_chance: f32,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct World {
+    pub seed: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Items {
+    pub path: String,
+    pub friction: f32,
+    pub respawn_lost: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Crops {
+    pub path: String,
 }
 
 #[derive(Serialize, Deserialize)]
-#[serde(rename_all = "kebab-case")]
-pub struct Plants {
-    pub wheat: Wheat,
+#[serde(default, rename_all = "kebab-case")]
+pub struct Trees {
+    pub path: String,
 }
 
 #[derive(Serialize, Deserialize)]
-#[serde(rename_all = "kebab-case")]
-pub struct Wheat {
-    pub growth_time: f32,
-    pub growth_variance: f32,
+#[serde(default, rename_all = "kebab-case")]
+pub struct Props {
+    pub path: String,
 }
 
 #[derive(Serialize, Deserialize)]
-#[serde(rename_all = "kebab-case")]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Banners {
+    pub path: String,
+    pub palette: Vec<[u8; 3]>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Export {
+    pub path: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Expansion {
+    pub item: ItemType,
+    pub base_cost: u32,
+    pub cost_growth: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Save {
+    pub path: String,
+    pub autosave_interval: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Replay {
+    pub record: bool,
+    pub replay: bool,
+    pub path: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
 pub struct Config {
+    pub version: u32,
     pub controls: Controls,
     pub window: Window,
     pub graphics: Graphics,
+    pub cloth: Cloth,
+    pub wind: Wind,
+    pub day: Day,
     pub tile: Tile,
-    pub plants: Plants,
+    pub world: World,
+    pub items: Items,
+    pub crops: Crops,
+    pub trees: Trees,
+    pub props: Props,
+    pub banners: Banners,
+    pub export: Export,
+    pub expansion: Expansion,
+    pub save: Save,
+    pub replay: Replay,
+}
+
+impl Controls {
+    // every key bound to an action, their edges are recorded with each step
+    #[inline]
+    pub fn keys(&self) -> impl Iterator<Item = &Key> {
+        let actions = vec![
+            &self.up,
+            &self.down,
+            &self.left,
+            &self.right,
+            &self.toggle_fullscreen,
+            &self.export_mesh,
+            &self.banner_pattern,
+            &self.banner_color,
+            &self.banner_accent,
+        ];
+
+        actions.into_iter().chain(&self.hotbar)
+    }
+}
+
+impl Default for Controls {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            up: Key::Up,
+            down: Key::Down,
+            left: Key::Left,
+            right: Key::Right,
+            toggle_fullscreen: Key::F,
+            export_mesh: Key::F8,
+            banner_pattern: Key::B,
+            banner_color: Key::N,
+            banner_accent: Key::M,
+            primary: MouseButton::Left,
+            secondary: MouseButton::Right,
+            camera_speed: 64.0,
+            zoom_min: 128.0,
+            zoom_max: 512.0,
+            zoom_speed: 0.1,
+            zoom_smoothing: 12.0,
+            item_offset: false,
+            hotbar: vec![
+                Key::Key1,
+                Key::Key2,
+                Key::Key3,
+                Key::Key4,
+                Key::Key5,
+                Key::Key6,
+                Key::Key7,
+                Key::Key8,
+                Key::Key9,
+            ],
+            auto_pickup: false,
+            pickup_radius: 24.0,
+            collector_radius: 48.0,
+            pickup_speed: 96.0,
+        }
+    }
+}
+
+impl Default for Graphics {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            custom_cursor: true,
+            always_show_stack_size: false,
+            d3_scale: 256,
+        }
+    }
+}
+
+impl Default for Cloth {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            rate: 60.0,
+            iterations: 8,
+            stiffness: 1.0,
+            shear_stiffness: 0.5,
+            bend_stiffness: 0.2,
+            drag: 4.0,
+            damping: 0.01,
+            tear_stretch: 3.0,
+            grab_radius: 6.0,
+            push_radius: 12.0,
+            push_strength: 0.5,
+        }
+    }
+}
+
+impl Default for Wind {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            direction: [-1.0, -1.0],
+            strength: 2.0,
+            gust_strength: 0.6,
+            gust_frequency: 0.3,
+            gust_size: 200.0,
+            turbulence: 0.4,
+        }
+    }
+}
+
+impl Default for Day {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            length: 600.0,
+            start: 0.3,
+            day_sky: [123, 216, 213],
+            dusk_sky: [242, 148, 99],
+            night_sky: [24, 30, 66],
+            dusk_shade: [255, 196, 166],
+            night_shade: [92, 104, 168],
+            moonlight: 0.3,
+        }
+    }
+}
+
+impl Default for Tile {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            grass_growth_time: 30.0,
+            grass_drop: ItemType::new("wheat_seed"),
+            grass_drop_chance: 0.2,
+        }
+    }
+}
+
+impl Default for Items {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            path: String::from("assets/items"),
+            friction: 6.0,
+            respawn_lost: false,
+        }
+    }
+}
+
+impl Default for Crops {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            path: String::from("assets/crops"),
+        }
+    }
+}
+
+impl Default for Trees {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            path: String::from("assets/trees"),
+        }
+    }
+}
+
+impl Default for Props {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            path: String::from("assets/props"),
+        }
+    }
+}
+
+impl Default for Banners {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            path: String::from("assets/banners"),
+            palette: vec![
+                [247, 16, 16],
+                [250, 250, 250],
+                [245, 196, 39],
+                [59, 125, 216],
+                [76, 193, 59],
+                [140, 63, 178],
+                [38, 38, 46],
+            ],
+        }
+    }
+}
+
+impl Default for Export {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            path: String::from("exports"),
+        }
+    }
+}
+
+impl Default for Expansion {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            item: ItemType::new("wood"),
+            base_cost: 2,
+            cost_growth: 1.25,
+        }
+    }
+}
+
+impl Default for Save {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            path: String::from("save.ron"),
+            autosave_interval: 60.0,
+        }
+    }
+}
+
+impl Default for Replay {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            record: false,
+            replay: false,
+            path: String::from("recording.ron"),
+        }
+    }
+}
+
+impl Default for Config {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            version: CONFIG_VERSION,
+            controls: Default::default(),
+            window: Default::default(),
+            graphics: Default::default(),
+            cloth: Default::default(),
+            wind: Default::default(),
+            day: Default::default(),
+            tile: Default::default(),
+            world: Default::default(),
+            items: Default::default(),
+            crops: Default::default(),
+            trees: Default::default(),
+            props: Default::default(),
+            banners: Default::default(),
+            export: Default::default(),
+            expansion: Default::default(),
+            save: Default::default(),
+            replay: Default::default(),
+        }
+    }
+}
+
+impl Config {
+    // missing keys fall back to their defaults, old versions are migrated
+    #[inline]
+    pub fn load(path: impl AsRef<Path>) -> ike::anyhow::Result<Self> {
+        let path = path.as_ref();
+
+        let mut value: toml::Value = read_to_string(path)?
+            .parse()
+            .map_err(|err| ike::anyhow::anyhow!("{} is not valid toml: {}", path.display(), err))?;
+
+        migrate(&mut value)?;
+
+        let config: Self = value
+            .try_into()
+            .map_err(|err| ike::anyhow::anyhow!("invalid value in {}: {}", path.display(), err))?;
+
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    // writes a commented default config first if there is none
+    #[inline]
+    pub fn load_or_create(path: impl AsRef<Path>) -> ike::anyhow::Result<Self> {
+        let path = path.as_ref();
+
+        if !path.exists() {
+            log::info!("no config found, writing defaults to {}", path.display());
+            fs::write(path, DEFAULT_CONFIG)?;
+        }
+
+        Self::load(path)
+    }
+
+    #[inline]
+    pub fn validate(&self) -> ike::anyhow::Result<()> {
+        let controls = &self.controls;
+
+        ensure!(
+            controls.camera_speed >= 0.0,
+            "controls.camera-speed must not be negative, got {}",
+            controls.camera_speed
+        );
+        ensure!(
+            controls.zoom_min > 0.0,
+            "controls.zoom-min must be greater than 0, got {}",
+            controls.zoom_min
+        );
+        ensure!(
+            controls.zoom_max >= controls.zoom_min,
+            "controls.zoom-max ({}) must not be less than controls.zoom-min ({})",
+            controls.zoom_max,
+            controls.zoom_min
+        );
+        ensure!(
+            controls.zoom_speed > 0.0 && controls.zoom_speed < 1.0,
+            "controls.zoom-speed must be between 0 and 1, got {}",
+            controls.zoom_speed
+        );
+        ensure!(
+            controls.zoom_smoothing > 0.0,
+            "controls.zoom-smoothing must be greater than 0, got {}",
+            controls.zoom_smoothing
+        );
+
+        ensure!(
+            controls.hotbar.len() <= HOTBAR_SIZE,
+            "controls.hotbar has {} keys but there are only {} slots",
+            controls.hotbar.len(),
+            HOTBAR_SIZE
+        );
+
+        ensure!(
+            controls.pickup_radius >= 0.0 && controls.collector_radius >= 0.0,
+            "controls.pickup-radius and controls.collector-radius must not be negative"
+        );
+        ensure!(
+            controls.pickup_speed > 0.0,
+            "controls.pickup-speed must be greater than 0, got {}",
+            controls.pickup_speed
+        );
+
+        ensure!(
+            (D3_SCALE_MIN..=D3_SCALE_MAX).contains(&self.graphics.d3_scale),
+            "graphics.d3-scale must be between {} and {}, got {}",
+            D3_SCALE_MIN,
+            D3_SCALE_MAX,
+            self.graphics.d3_scale
+        );
+
+        let cloth = &self.cloth;
+
+        ensure!(
+            (10.0..=480.0).contains(&cloth.rate),
+            "cloth.rate must be between 10 and 480, got {}",
+            cloth.rate
+        );
+        ensure!(
+            (1..=64).contains(&cloth.iterations),
+            "cloth.iterations must be between 1 and 64, got {}",
+            cloth.iterations
+        );
+        ensure!(
+            [cloth.stiffness, cloth.shear_stiffness, cloth.bend_stiffness]
+                .iter()
+                .all(|stiffness| (0.0..=1.0).contains(stiffness)),
+            "cloth.stiffness, cloth.shear-stiffness and cloth.bend-stiffness must be \
+             between 0 and 1"
+        );
+        ensure!(
+            cloth.drag >= 0.0,
+            "cloth.drag must not be negative, got {}",
+            cloth.drag
+        );
+        ensure!(
+            (0.0..1.0).contains(&cloth.damping),
+            "cloth.damping must be at least 0 and less than 1, got {}",
+            cloth.damping
+        );
+        ensure!(
+            cloth.tear_stretch == 0.0 || cloth.tear_stretch > 1.0,
+            "cloth.tear-stretch must be 0 or greater than 1, got {}",
+            cloth.tear_stretch
+        );
+        ensure!(
+            cloth.grab_radius >= 0.0 && cloth.push_radius >= 0.0 && cloth.push_strength >= 0.0,
+            "cloth.grab-radius, cloth.push-radius and cloth.push-strength must not be negative"
+        );
+
+        ensure!(
+            self.wind.strength >= 0.0 && self.wind.gust_size > 0.0,
+            "wind.strength must not be negative and wind.gust-size must be greater than 0"
+        );
+
+        ensure!(
+            self.day.length > 0.0,
+            "day.length must be greater than 0, got {}",
+            self.day.length
+        );
+        ensure!(
+            (0.0..=1.0).contains(&self.day.start) && (0.0..=1.0).contains(&self.day.moonlight),
+            "day.start and day.moonlight must be between 0 and 1"
+        );
+
+        ensure!(
+            self.tile.grass_growth_time > 0.0,
+            "tile.grass-growth-time must be greater than 0, got {}",
+            self.tile.grass_growth_time
+        );
+        ensure!(
+            (0.0..=1.0).contains(&self.tile.grass_drop_chance),
+            "tile.grass-drop-chance must be between 0 and 1, got {}",
+            self.tile.grass_drop_chance
+        );
+
+        ensure!(
+            self.items.friction >= 0.0,
+            "items.friction must not be negative, got {}",
+            self.items.friction
+        );
+
+        ensure!(
+            !self.banners.palette.is_empty(),
+            "banners.palette must have at least one color"
+        );
+
+        ensure!(
+            self.expansion.cost_growth >= 1.0,
+            "expansion.cost-growth must be at least 1, got {}",
+            self.expansion.cost_growth
+        );
+
+        ensure!(
+            self.save.autosave_interval > 0.0,
+            "save.autosave-interval must be greater than 0, got {}",
+            self.save.autosave_interval
+        );
+
+        Ok(())
+    }
+}
+
+// upgrades an older config in place one version at a time
+#[inline]
+fn migrate(value: &mut toml::Value) -> ike::anyhow::Result<()> {
+    let table = match value.as_table_mut() {
+        Some(table) => table,
+        None => ike::anyhow::bail!("config must be a table"),
+    };
+
+    let version = match table.get("version") {
+        Some(version) => match version.as_integer() {
+            Some(version) if version > 0 => version as u32,
+            _ => ike::anyhow::bail!("version must be a positive integer, got {}", version),
+        },
+        None => 1,
+    };
+
+    ensure!(
+        version <= CONFIG_VERSION,
+        "config version {} is newer than this game supports ({})",
+        version,
+        CONFIG_VERSION
+    );
+
+    // 1 -> 2: wheat settings moved to the crop definitions
+    if version < 2 && table.remove("plants").is_some() {
+        log::warn!(
+            "[plants] is no longer used, crop growth is set in the files in [crops] path instead"
+        );
+    }
+
+    // 2 -> 3: every pole simulates its own cloth
+    if version < 3 {
+        let graphics = table.get_mut("graphics").and_then(|graphics| graphics.as_table_mut());
+
+        if let Some(graphics) = graphics {
+            graphics.remove("instance-cloth");
+        }
+    }
+
+    if version < CONFIG_VERSION {
+        log::warn!(
+            "config is version {}, migrated to {}, update it to silence this warning",
+            version,
+            CONFIG_VERSION
+        );
+    }
+
+    table.insert(
+        String::from("version"),
+        toml::Value::Integer(CONFIG_VERSION as i64),
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn migrated(source: &str) -> toml::Value {
+        let mut value: toml::Value = source.parse().unwrap();
+        migrate(&mut value).unwrap();
+
+        value
+    }
+
+    fn version(value: &toml::Value) -> Option<i64> {
+        value.get("version")?.as_integer()
+    }
+
+    #[test]
+    fn default_config_matches_defaults() {
+        let config: Config = migrated(DEFAULT_CONFIG).try_into().unwrap();
+
+        config.validate().unwrap();
+        Config::default().validate().unwrap();
+
+        // compared through toml so every section is checked without deriving `PartialEq`
+        assert_eq!(
+            toml::Value::try_from(&config).unwrap(),
+            toml::Value::try_from(Config::default()).unwrap()
+        );
+    }
+
+    #[test]
+    fn migrates_v1_plants() {
+        let value = migrated(
+            "[plants]\n\
+             wheat-growth-time = 10.0\n\
+             [graphics]\n\
+             custom-cursor = false\n",
+        );
+
+        assert_eq!(version(&value), Some(CONFIG_VERSION as i64));
+        assert!(value.get("plants").is_none());
+
+        let config: Config = value.try_into().unwrap();
+        assert!(!config.graphics.custom_cursor);
+    }
+
+    #[test]
+    fn migrates_v2_instance_cloth() {
+        let value = migrated(
+            "version = 2\n\
+             [graphics]\n\
+             instance-cloth = true\n\
+             d3-scale = 128\n",
+        );
+
+        assert_eq!(version(&value), Some(CONFIG_VERSION as i64));
+        assert!(value["graphics"].get("instance-cloth").is_none());
+
+        let config: Config = value.try_into().unwrap();
+        assert_eq!(config.graphics.d3_scale, 128);
+    }
+
+    #[test]
+    fn newer_versions_are_rejected() {
+        let mut value: toml::Value = format!("version = {}", CONFIG_VERSION + 1).parse().unwrap();
+
+        assert!(migrate(&mut value).is_err());
+    }
 }
@@ -0,0 +1,196 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use ike::prelude::*;
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::item::ItemType;
+
+#[inline]
+fn default_chance() -> f32 {
+    1.0
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Yield {
+    pub item: ItemType,
+    pub count: u32,
+    #[serde(default = "default_chance")]
+    pub chance: f32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CropDef {
+    pub seed: ItemType,
+    pub growth_time: f32,
+    pub growth_variance: f32,
+    pub stages: Vec<String>,
+    pub yields: Vec<Yield>,
+}
+
+pub struct Crop {
+    pub id: String,
+    pub def: CropDef,
+    // empty when running headless
+    pub stages: Vec<Texture>,
+}
+
+impl Crop {
+    #[inline]
+    pub fn load(id: String, path: &Path, textures: bool) -> ike::anyhow::Result<Self> {
+        let def: CropDef = toml::from_str(&fs::read_to_string(path)?)?;
+
+        if def.stages.is_empty() {
+            ike::anyhow::bail!("crop '{}' has no stages", id);
+        }
+
+        if def.growth_time <= 0.0 {
+            ike::anyhow::bail!(
+                "crop '{}' growth-time must be greater than 0, got {}",
+                id,
+                def.growth_time
+            );
+        }
+
+        if !(0.0..1.0).contains(&def.growth_variance) {
+            ike::anyhow::bail!(
+                "crop '{}' growth-variance must be between 0 and 1, got {}",
+                id,
+                def.growth_variance
+            );
+        }
+
+        let stages = if textures {
+            def.stages
+                .iter()
+                .map(|path| Texture::load(path))
+                .collect::<Result<_, _>>()?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self { id, def, stages })
+    }
+
+    #[inline]
+    pub fn stage_count(&self) -> usize {
+        self.def.stages.len()
+    }
+
+    #[inline]
+    pub fn stage_texture(&self, growth: f32) -> Option<&Texture> {
+        let stage = (growth.max(0.0) as usize).min(self.stage_count() - 1);
+
+        self.stages.get(stage)
+    }
+
+    // rolls the harvest, yields of the same item are merged into one stack
+    #[inline]
+    pub fn roll_yields(&self, rng: &mut impl Rng) -> Vec<(ItemType, u32)> {
+        let mut yields: Vec<(ItemType, u32)> = Vec::new();
+
+        for y in &self.def.yields {
+            if y.count == 0 || rng.gen_range(0.0..1.0) >= y.chance {
+                continue;
+            }
+
+            match yields.iter_mut().find(|(ty, _)| *ty == y.item) {
+                Some((_, count)) => *count += y.count,
+                None => yields.push((y.item.clone(), y.count)),
+            }
+        }
+
+        yields
+    }
+}
+
+// kept sorted by id so crops sharing a seed always resolve to the same one
+#[derive(Default)]
+pub struct Crops {
+    pub crops: BTreeMap<String, Crop>,
+}
+
+impl Crops {
+    // loads every `*.toml` file in `path`, the file name is used as the crop id
+    #[inline]
+    pub fn load(path: impl AsRef<Path>) -> ike::anyhow::Result<Self> {
+        Self::load_with(path, true)
+    }
+
+    // loads the definitions without their sprites, for simulating without a window
+    #[inline]
+    pub fn load_headless(path: impl AsRef<Path>) -> ike::anyhow::Result<Self> {
+        Self::load_with(path, false)
+    }
+
+    #[inline]
+    fn load_with(path: impl AsRef<Path>, textures: bool) -> ike::anyhow::Result<Self> {
+        let mut crops = Self::default();
+
+        for entry in fs::read_dir(path)? {
+            let path = entry?.path();
+
+            if path.extension().map_or(true, |ext| ext != "toml") {
+                continue;
+            }
+
+            let id = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(id) => id.to_string(),
+                None => continue,
+            };
+
+            let crop = Crop::load(id.clone(), &path, textures)
+                .map_err(|err| err.context(format!("failed to load crop '{}'", id)))?;
+
+            crops.crops.insert(id, crop);
+        }
+
+        Ok(crops)
+    }
+
+    #[inline]
+    pub fn get(&self, id: &str) -> Option<&Crop> {
+        self.crops.get(id)
+    }
+
+    // the first crop by id grown from `seed`
+    #[inline]
+    pub fn from_seed(&self, seed: &ItemType) -> Option<&Crop> {
+        self.crops.values().find(|crop| crop.def.seed == *seed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn crop(id: &str, seed: &str) -> Crop {
+        Crop {
+            id: id.to_string(),
+            def: CropDef {
+                seed: ItemType::new(seed),
+                growth_time: 1.0,
+                growth_variance: 0.0,
+                stages: vec![String::from("stage")],
+                yields: Vec::new(),
+            },
+            stages: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn shared_seeds_pick_the_first_crop() {
+        let mut crops = Crops::default();
+
+        for id in ["wheat", "barley", "rye"].iter() {
+            crops.crops.insert(id.to_string(), crop(id, "grain_seed"));
+        }
+
+        let seed = ItemType::new("grain_seed");
+
+        assert_eq!(crops.from_seed(&seed).map(|crop| crop.id.as_str()), Some("barley"));
+        assert!(crops.from_seed(&ItemType::new("carrot_seed")).is_none());
+    }
+}
@@ -0,0 +1,92 @@
+use ike::prelude::*;
+
+use crate::config;
+
+// how the farm is lit at one moment of the day, read by both the 2d and 3d passes
+#[derive(Clone, Copy, Debug)]
+pub struct Daylight {
+    // 0 is midnight and 0.5 is noon
+    pub time_of_day: f32,
+    // drawn behind the farm in place of the clear color
+    pub sky: Vec3,
+    // the sky and the farm are multiplied by this, the ui is laid over them afterwards
+    pub shade: Vec3,
+    // direction towards the sun, or the moon at night
+    pub light_dir: Vec3,
+    // brightness of the light along `light_dir`, none at the horizon
+    pub light: f32,
+}
+
+impl Daylight {
+    // the clock is derived from the world time so it is saved and replayed with it
+    #[inline]
+    pub fn at(cfg: &config::Day, time: f32) -> Self {
+        let time_of_day = (cfg.start + time / cfg.length).rem_euclid(1.0);
+
+        // the sun rises at a quarter of the day and sets at three quarters
+        let angle = (time_of_day - 0.25) * std::f32::consts::TAU;
+        let height = angle.sin();
+
+        // 1 through the day, 0 through the night and strongest dusk at the horizon
+        let day = smoothstep(-0.15, 0.3, height);
+        let dusk = 1.0 - (height.abs() / 0.3).min(1.0);
+
+        let sky = rgb(cfg.night_sky)
+            .lerp(rgb(cfg.day_sky), day)
+            .lerp(rgb(cfg.dusk_sky), dusk);
+
+        let shade = rgb(cfg.night_shade)
+            .lerp(Vec3::ONE, day)
+            .lerp(rgb(cfg.dusk_shade), dusk);
+
+        // the sun crosses the sky from east to west, the moon follows on the other side
+        let sun = Vec3::new(angle.cos(), height, 0.5).normalize();
+        let light_dir = if height >= 0.0 { sun } else { -sun };
+
+        // the light fades out at the horizon so the switch between the sun and the
+        // moon doesn't flip which side of everything is lit
+        let horizon = smoothstep(0.0, 0.15, height.abs());
+
+        Self {
+            time_of_day,
+            sky,
+            shade,
+            light_dir,
+            light: (cfg.moonlight + (1.0 - cfg.moonlight) * day) * horizon,
+        }
+    }
+}
+
+#[inline]
+fn rgb(color: [u8; 3]) -> Vec3 {
+    Vec3::new(color[0] as f32, color[1] as f32, color[2] as f32) / 255.0
+}
+
+#[inline]
+fn smoothstep(min: f32, max: f32, x: f32) -> f32 {
+    let t = ((x - min) / (max - min)).max(0.0).min(1.0);
+
+    t * t * (3.0 - 2.0 * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn light_never_jumps() {
+        let cfg = config::Day::default();
+        let steps = 10_000;
+
+        let light = |step: u32| {
+            let daylight = Daylight::at(&cfg, step as f32 / steps as f32 * cfg.length);
+            daylight.light_dir * daylight.light
+        };
+
+        for step in 0..steps {
+            let jump = light(step).distance(light(step + 1));
+
+            assert!(jump < 0.01, "light jumps by {} at step {}", jump, step);
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use std::collections::HashMap;
+
+use ike::prelude::*;
+
+use crate::{config::Config, tile::Tile};
+
+// size of the starting 3x3 island, tiles beyond this have been bought
+pub const STARTING_TILES: usize = 9;
+
+#[inline]
+pub fn neighbors(position: IVec2) -> [IVec2; 4] {
+    [
+        position + IVec2::new(1, 0),
+        position + IVec2::new(-1, 0),
+        position + IVec2::new(0, 1),
+        position + IVec2::new(0, -1),
+    ]
+}
+
+#[inline]
+pub fn can_expand(tiles: &HashMap<IVec2, Tile>, position: IVec2) -> bool {
+    !tiles.contains_key(&position)
+        && neighbors(position)
+            .iter()
+            .any(|neighbor| tiles.contains_key(neighbor))
+}
+
+#[inline]
+pub fn cost(tiles: &HashMap<IVec2, Tile>, cfg: &Config) -> u32 {
+    let bought = tiles.len().saturating_sub(STARTING_TILES) as i32;
+
+    (cfg.expansion.base_cost as f32 * cfg.expansion.cost_growth.powi(bought)).ceil() as u32
+}
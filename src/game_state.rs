@@ -1,4 +1,4 @@
-use std::{collections::HashMap, fs::read_to_string};
+use std::{collections::BTreeMap, fs, path::Path};
 
 use ike::prelude::*;
 use kira::manager::AudioManager;
@@ -6,16 +6,32 @@ use kira::manager::AudioManager;
 use crate::{
     assets::Assets,
     audio::Audio,
+    banner::{Banner, Patterns},
     cloth::Cloth,
-    config::Config,
-    iso::{from_iso, to_iso},
-    item::Items,
-    tile::Tile,
+    config::{Config, CONFIG_PATH},
+    crop::Crops,
+    daylight::Daylight,
+    input::Input,
+    inventory,
+    iso::{d3_rotation, d3_tile_position, from_iso, tile_position, to_iso, TILE_SIZE},
+    item_kind::{ItemKinds, UseAction},
+    model,
+    prop::Props,
+    render::{MeshBatch, UI_ORIGIN},
+    replay::{Recorder, Recording},
+    save::Save,
+    species::TreeSpecies,
+    tile::{cloth_offset, StructureKind, Tile},
+    watch::Watcher,
+    wind,
+    world::{World, WorldEvent},
 };
 
 pub struct OrthographicCamera {
     pub projection: OrthographicProjection,
     pub transform: Transform2d,
+    // projection size the camera is smoothly zooming towards
+    pub zoom: f32,
 }
 
 impl OrthographicCamera {
@@ -27,9 +43,20 @@ impl OrthographicCamera {
                 ..Default::default()
             },
             transform: Transform2d::IDENTITY,
+            zoom: 256.0,
         }
     }
 
+    // sets the projection size while keeping the world point under `anchor` in place,
+    // `anchor` is in screen space ranging from -0.5 to 0.5 vertically
+    #[inline]
+    pub fn zoom_around(&mut self, size: f32, anchor: Vec2) {
+        let world = anchor * self.projection.size + self.transform.translation;
+
+        self.projection.size = size;
+        self.transform.translation = world - anchor * size;
+    }
+
     #[inline]
     pub fn id(&self) -> Id<Camera> {
         self.projection.id
@@ -51,13 +78,37 @@ pub struct GameState {
     pub audio: Audio,
     pub audio_manager: AudioManager,
     pub d3_buffer: FrameBuffer,
+    // the ui drawn by `UiPass`, laid over the farm after the daylight shade
+    pub ui_buffer: FrameBuffer,
     pub config: Config,
-    pub cloth: Cloth,
+    pub item_kinds: ItemKinds,
+    pub crops: Crops,
+    pub species: TreeSpecies,
+    pub props: Props,
+    pub patterns: Patterns,
+    // cloth flying the selected banner over the tile a pole would be placed on
+    pub banner_preview: Cloth,
+    pub preview_banner: Banner,
+    pub preview_tile: Option<IVec2>,
     pub main_camera: OrthographicCamera,
-    pub items: Items,
-    pub tiles: HashMap<IVec2, Tile>,
-    pub time: f32,
+    // looks at the ui around `UI_ORIGIN` at the same zoom as `main_camera`
+    pub ui_camera: OrthographicCamera,
+    pub world: World,
+    pub daylight: Daylight,
+    // meshes drawn into the 3d pass this frame
+    pub meshes: MeshBatch,
+    pub recorder: Option<Recorder>,
+    pub replay: Option<std::vec::IntoIter<Input>>,
+    pub autosave_timer: f32,
     pub mouse_position: Vec2,
+    pub config_watcher: Watcher,
+    pub crops_watcher: Watcher,
+    pub species_watcher: Watcher,
+    pub props_watcher: Watcher,
+    pub patterns_watcher: Watcher,
+    pub items_watcher: Watcher,
+    // errors from the last hot reload of each watched source, shown on screen
+    pub reload_errors: BTreeMap<&'static str, String>,
 }
 
 impl State for GameState {
@@ -69,11 +120,21 @@ impl State for GameState {
     }
 
     fn update(&mut self, ctx: &mut UpdateCtx) {
+        self.hot_reload(ctx);
+
         // scale camera to screen
         self.main_camera.projection.scale(ctx.window.size);
 
-        // advance time
-        self.time += ctx.delta_time;
+        // autosave
+        self.autosave_timer += ctx.delta_time;
+
+        if self.autosave_timer >= self.config.save.autosave_interval && !self.config.replay.replay {
+            self.autosave_timer = 0.0;
+
+            if let Err(err) = Save::from_state(self).write(&self.config.save.path) {
+                log::error!("failed to save game: {}", err);
+            }
+        }
 
         if ctx
             .key_input
@@ -124,75 +185,127 @@ impl State for GameState {
             (ctx.mouse.position - ctx.window.size.as_f32() / 2.0) / ctx.window.size.y as f32;
         mouse_pos.y *= -1.0;
 
+        // zoom camera with mouse wheel, centered on the cursor
+        let controls = &self.config.controls;
+
+        if ctx.mouse.wheel_delta.y != 0.0 {
+            let steps = ctx.mouse.wheel_delta.y;
+            let zoom = self.main_camera.zoom * (1.0 - controls.zoom_speed).powf(steps);
+            self.main_camera.zoom = zoom.max(controls.zoom_min).min(controls.zoom_max);
+        }
+
+        let t = 1.0 - (-controls.zoom_smoothing * ctx.delta_time).exp();
+        let size = self.main_camera.projection.size;
+
+        self.main_camera
+            .zoom_around(size + (self.main_camera.zoom - size) * t, mouse_pos);
+
+        // keep the farm in view
+        let (min, max) = self.world.bounds();
+        let translation = &mut self.main_camera.transform.translation;
+        *translation = translation.max(min).min(max);
+
         self.mouse_position =
             mouse_pos * self.main_camera.projection.size + self.main_camera.transform.translation;
 
-        let mouse = to_iso(self.mouse_position, Vec2::splat(40.0));
-
-        // update tiles
-        for (position, tile) in self.tiles.iter_mut() {
-            // if tile hovered
-            if mouse.x > position.x as f32 - 0.5
-                && mouse.x < position.x as f32 + 0.5
-                && mouse.y > position.y as f32 - 0.5
-                && mouse.y < position.y as f32 + 0.5
-            {
-                let position = from_iso(position.as_f32(), Vec2::splat(40.0));
-
-                tile.hovered(
-                    ctx,
-                    &self.config,
-                    &mut self.audio,
-                    position,
-                    &mut self.items,
-                );
+        // the hotbar is laid out at the default zoom, see `inventory::slot_at`
+        let hotbar = inventory::slot_at(mouse_pos * 256.0);
+
+        // step the simulation
+        let mut input = Input::from_ctx(ctx, self.mouse_position, hotbar, &self.config);
+
+        if let Some(ref mut replay) = self.replay {
+            match replay.next() {
+                Some(recorded) => {
+                    input = recorded;
+                    self.mouse_position = input.mouse;
+                }
+                None => {
+                    log::info!("replay finished");
+                    self.replay = None;
+                }
             }
-
-            tile.update(ctx, &mut self.items, &self.config);
         }
 
-        self.items.update(
-            ctx,
-            &self.tiles,
-            self.mouse_position,
-            self.time,
-            &self.config,
-        );
+        if let Some(ref mut recorder) = self.recorder {
+            if let Err(err) = recorder.record(&input) {
+                log::error!("failed to record input, recording stopped: {}", err);
+                self.recorder = None;
+            }
+        }
 
-        if self.config.graphics.instance_cloth {
-            self.cloth.update(
-                ctx.delta_time,
-                Vec3::new(-1.25, 0.0, -1.25),
-                Vec2::new(
-                    (self.time * 10.0).cos() - 1.0,
-                    (self.time * 10.0).sin() - 1.0,
-                ),
+        let events = self
+            .world
+            .step(
+                &input,
+                &self.config,
+                &self.item_kinds,
+                &self.crops,
+                &self.species,
+                &self.patterns,
             );
+
+        for event in events {
+            match event {
+                WorldEvent::Hit => self.audio.play_hit(),
+            }
         }
 
-        self.items
-            .render(ctx, &mut self.assets, &self.config);
+        if input.key(&self.config.controls.export_mesh).pressed {
+            self.export_mesh();
+        }
+
+        self.update_banner_preview(ctx.delta_time);
+
+        self.daylight = Daylight::at(&self.config.day, self.world.time);
+
+        self.world
+            .items
+            .render(ctx, &mut self.assets, &self.item_kinds, &self.config);
+
+        // the ui is drawn around `UI_ORIGIN` as if the camera was there
+        self.ui_camera.projection.size = self.main_camera.projection.size;
+        self.ui_camera.projection.scale(ctx.window.size);
+
+        let ui_mouse = UI_ORIGIN + self.mouse_position - self.main_camera.transform.translation;
 
         if self.config.graphics.custom_cursor {
             let mut sprite = Sprite::new(
                 &self.assets.cursor,
-                Transform2d::from_translation(self.mouse_position),
+                Transform2d::from_translation(ui_mouse),
             );
             sprite.depth = 500.0;
 
             ctx.draw(&sprite);
         }
 
-        // draw tiles
-        for (position, tile) in &self.tiles {
-            let d = position.x as f32 + position.y as f32;
+        self.world.inventory.render(
+            ctx,
+            &self.assets,
+            &self.item_kinds,
+            UI_ORIGIN,
+            self.main_camera.projection.size / 256.0,
+            ui_mouse,
+        );
+
+        // show hot reload errors at the top of the screen
+        let top = UI_ORIGIN + Vec2::Y * (self.main_camera.projection.size / 2.0 - 16.0);
+
+        for (i, (source, error)) in self.reload_errors.iter().enumerate() {
+            let mut text = TextSprite::new(
+                &self.assets.font,
+                Transform2d::from_translation(top - Vec2::Y * i as f32 * 16.0),
+            );
 
-            // calculate tile floating offset
-            let offset = (d * 2.0 + self.time * 0.5).sin();
+            text.text = format!("failed to reload {}: {}", source, error);
+            text.depth = 400.0;
 
-            // convert from isometric to cartesian
-            let mut tile_pos = from_iso(position.as_f32(), Vec2::splat(40.0));
-            tile_pos += Vec2::new(0.0, offset);
+            ctx.draw(&text);
+        }
+
+        // draw tiles
+        for (position, tile) in &self.world.tiles {
+            let tile_pos = tile_position(*position, self.world.time);
 
             let texture = tile.texture(&mut self.assets);
 
@@ -210,34 +323,42 @@ impl State for GameState {
                 ctx,
                 tile_pos,
                 &mut self.assets,
-                &self.config,
+                &self.crops,
+            );
+        }
+
+        // draw purchasable tile preview
+        if let Some((position, affordable)) = self.world.ghost_tile {
+            let tile_pos = tile_position(position, self.world.time);
+
+            let texture = if affordable {
+                &self.assets.ghost_tile
+            } else {
+                &self.assets.ghost_tile_locked
+            };
+
+            let mut sprite = Sprite::new(
+                texture,
+                Transform2d::from_translation(tile_pos + Vec2::new(0.0, -8.0)),
             );
+
+            sprite.depth = -(tile_pos.y + 8.0) / 0.5f32.asin().tan();
+
+            ctx.draw(&sprite);
         }
 
         // 3d
         let mut transform = Transform3d::IDENTITY;
-        transform.rotation = Quat::from_rotation_x(0.5f32.asin());
-        transform.rotation *= Quat::from_rotation_y(std::f32::consts::FRAC_PI_4);
+        transform.rotation = d3_rotation();
 
-        for (position, tile) in &self.tiles {
-            let d = position.x as f32 + position.y as f32;
+        self.meshes.clear();
 
-            // calculate tile floating offset
-            let offset = (d * 2.0 + self.time * 0.5).sin() * 1.0;
+        self.render_banner_preview(ctx, &transform);
 
-            let position = Vec3::new(
-                position.x as f32 * 40.0 * std::f32::consts::FRAC_1_SQRT_2,
-                offset,
-                -position.y as f32 * 40.0 * std::f32::consts::FRAC_1_SQRT_2,
-            );
+        for (position, tile) in &self.world.tiles {
+            let position = d3_tile_position(*position, self.world.time);
 
-            tile.render_mesh(
-                ctx,
-                position,
-                &transform,
-                &self.cloth,
-                &self.config,
-            );
+            tile.render_mesh(&mut self.meshes, position, &transform, &self.props);
         }
 
         ctx.views.render_main_view(
@@ -249,31 +370,325 @@ impl State for GameState {
 impl GameState {
     pub fn load() -> ike::anyhow::Result<Self> {
         let mut audio_manager = AudioManager::new(Default::default())?;
-        let mut tiles = HashMap::new();
-
-        for x in -1..=1 {
-            for y in -1..=1 {
-                tiles.insert(IVec2::new(x, y), Tile::grass_plain());
-            }
-        }
 
-        let config = read_to_string("./config.toml")?;
+        let config = Config::load_or_create(CONFIG_PATH)?;
 
         let audio = Audio::load(&mut audio_manager)?;
         let assets = Assets::load()?;
-
-        Ok(Self {
+        let item_kinds = ItemKinds::load(&config.items.path)?;
+        let crops = Crops::load(&config.crops.path)?;
+        let species = TreeSpecies::load(&config.trees.path)?;
+        let props = Props::load(&config.props.path)?;
+        let patterns = Patterns::load(&config.banners.path)?;
+
+        let save_path = config.save.path.clone();
+        let crops_watcher = Watcher::new(&config.crops.path);
+        let species_watcher = Watcher::new(&config.trees.path);
+        let props_watcher = Watcher::new(&config.props.path);
+        let patterns_watcher = Watcher::new(&config.banners.path);
+        let items_watcher = Watcher::new(&config.items.path);
+        let seed = config.world.seed.unwrap_or_else(rand::random);
+        let daylight = Daylight::at(&config.day, 0.0);
+
+        let mut ui_camera = OrthographicCamera::new();
+        ui_camera.transform.translation = UI_ORIGIN;
+
+        let mut state = Self {
             assets,
             audio,
             audio_manager,
             d3_buffer: Default::default(),
-            config: toml::from_str(&config)?,
-            cloth: Cloth::generate(15, 4),
+            ui_buffer: Default::default(),
+            config,
+            item_kinds,
+            crops,
+            species,
+            props,
+            patterns,
+            banner_preview: Cloth::banner(),
+            preview_banner: Banner::default(),
+            preview_tile: None,
             main_camera: OrthographicCamera::new(),
-            items: Default::default(),
-            tiles,
-            time: 0.0,
+            ui_camera,
+            world: World::new(seed),
+            daylight,
+            meshes: MeshBatch::default(),
+            recorder: None,
+            replay: None,
+            autosave_timer: 0.0,
             mouse_position: Default::default(),
-        })
+            config_watcher: Watcher::new(CONFIG_PATH),
+            crops_watcher,
+            species_watcher,
+            props_watcher,
+            patterns_watcher,
+            items_watcher,
+            reload_errors: BTreeMap::new(),
+        };
+
+        // continue from the last save if there is one
+        if Path::new(&save_path).exists() {
+            match Save::read(&save_path) {
+                Ok(save) => save.apply(&mut state),
+                // keep the broken save around for a bug report and start a fresh farm
+                Err(err) => {
+                    let bad_path = format!("{}.bad", save_path);
+
+                    log::error!("failed to load save, moving it to '{}': {:#}", bad_path, err);
+
+                    if let Err(err) = fs::rename(&save_path, &bad_path) {
+                        log::error!("failed to move broken save: {}", err);
+                    }
+                }
+            }
+        }
+
+        if state.config.replay.replay {
+            let recording = Recording::read(&state.config.replay.path)?;
+
+            recording.start.apply(&mut state);
+            state.replay = Some(recording.frames.into_iter());
+        } else if state.config.replay.record {
+            let start = Save::from_state(&state);
+
+            state.recorder = Some(Recorder::create(&state.config.replay.path, &start)?);
+        }
+
+        Ok(state)
+    }
+
+    // re-applies config.toml and the crop and item definitions when they change on disk,
+    // errors are shown on screen and the previous values are kept
+    #[inline]
+    fn hot_reload(&mut self, ctx: &mut UpdateCtx) {
+        if self.config_watcher.changed(ctx.delta_time) {
+            match Config::load(CONFIG_PATH) {
+                Ok(config) => {
+                    ctx.window.cursor_visible = !config.graphics.custom_cursor;
+
+                    if config.crops.path != self.config.crops.path {
+                        self.crops_watcher = Watcher::new(&config.crops.path);
+                        self.reload_crops(&config.crops.path);
+                    }
+
+                    if config.trees.path != self.config.trees.path {
+                        self.species_watcher = Watcher::new(&config.trees.path);
+                        self.reload_species(&config.trees.path);
+                    }
+
+                    if config.props.path != self.config.props.path {
+                        self.props_watcher = Watcher::new(&config.props.path);
+                        self.reload_props(&config.props.path);
+                    }
+
+                    if config.banners.path != self.config.banners.path {
+                        self.patterns_watcher = Watcher::new(&config.banners.path);
+                        self.reload_patterns(&config.banners.path);
+                    }
+
+                    if config.items.path != self.config.items.path {
+                        self.items_watcher = Watcher::new(&config.items.path);
+                        self.reload_items(&config.items.path);
+                    }
+
+                    self.config = config;
+                    self.reload_errors.remove("config");
+                }
+                Err(err) => self.reload_error("config", err),
+            }
+        }
+
+        if self.crops_watcher.changed(ctx.delta_time) {
+            let path = self.config.crops.path.clone();
+            self.reload_crops(&path);
+        }
+
+        if self.species_watcher.changed(ctx.delta_time) {
+            let path = self.config.trees.path.clone();
+            self.reload_species(&path);
+        }
+
+        if self.props_watcher.changed(ctx.delta_time) {
+            let path = self.config.props.path.clone();
+            self.reload_props(&path);
+        }
+
+        if self.patterns_watcher.changed(ctx.delta_time) {
+            let path = self.config.banners.path.clone();
+            self.reload_patterns(&path);
+        }
+
+        if self.items_watcher.changed(ctx.delta_time) {
+            let path = self.config.items.path.clone();
+            self.reload_items(&path);
+        }
+    }
+
+    #[inline]
+    fn reload_crops(&mut self, path: &str) {
+        match Crops::load(path) {
+            Ok(crops) => {
+                self.crops = crops;
+                self.reload_errors.remove("crops");
+            }
+            Err(err) => self.reload_error("crops", err),
+        }
+    }
+
+    // only trees planted after the reload use the new definitions
+    #[inline]
+    fn reload_species(&mut self, path: &str) {
+        match TreeSpecies::load(path) {
+            Ok(species) => {
+                self.species = species;
+                self.reload_errors.remove("trees");
+            }
+            Err(err) => self.reload_error("trees", err),
+        }
+    }
+
+    #[inline]
+    fn reload_props(&mut self, path: &str) {
+        match Props::load(path) {
+            Ok(props) => {
+                self.props = props;
+                self.reload_errors.remove("props");
+            }
+            Err(err) => self.reload_error("props", err),
+        }
+    }
+
+    #[inline]
+    fn reload_patterns(&mut self, path: &str) {
+        match Patterns::load(path) {
+            Ok(patterns) => {
+                self.patterns = patterns;
+                self.reload_errors.remove("banners");
+            }
+            Err(err) => self.reload_error("banners", err),
+        }
+    }
+
+    #[inline]
+    fn reload_items(&mut self, path: &str) {
+        match ItemKinds::load(path) {
+            Ok(kinds) => {
+                self.item_kinds = kinds;
+                self.reload_errors.remove("items");
+            }
+            Err(err) => self.reload_error("items", err),
+        }
+    }
+
+    // shows the selected banner over the empty tile under the cursor while a pole is held
+    #[inline]
+    fn update_banner_preview(&mut self, delta_time: f32) {
+        let pole = UseAction::PlaceStructure {
+            structure: StructureKind::Pole,
+        };
+
+        let holding_pole = self
+            .world
+            .inventory
+            .selected_ty()
+            .map_or(false, |ty| self.item_kinds.use_action(ty) == pole);
+
+        let tile = to_iso(self.mouse_position, Vec2::splat(TILE_SIZE))
+            .round()
+            .as_i32();
+
+        let empty = matches!(
+            self.world.tiles.get(&tile),
+            Some(Tile::Grass {
+                structure: None,
+                ..
+            })
+        );
+
+        if !holding_pole || !empty {
+            self.preview_tile = None;
+            return;
+        }
+
+        // start hanging again when moved to another tile
+        if self.preview_tile != Some(tile) {
+            self.banner_preview = Cloth::banner();
+            self.preview_banner = Banner::default();
+            self.preview_tile = Some(tile);
+        }
+
+        if self.preview_banner != self.world.inventory.banner {
+            self.preview_banner = self.world.inventory.banner.clone();
+            self.banner_preview.paint(&self.preview_banner);
+        }
+
+        let wind = wind::sample(
+            &self.config.wind,
+            self.world.time,
+            from_iso(tile.as_f32(), Vec2::splat(TILE_SIZE)),
+        );
+
+        self.banner_preview.update(delta_time, wind, &self.config.cloth);
+    }
+
+    #[inline]
+    fn render_banner_preview(&mut self, ctx: &mut UpdateCtx, transform: &Transform3d) {
+        let tile = match self.preview_tile {
+            Some(tile) => tile,
+            None => return,
+        };
+
+        let tile_pos = tile_position(tile, self.world.time);
+
+        let mut sprite = Sprite::new(
+            &self.assets.pole,
+            Transform2d::from_translation(tile_pos + Vec2::new(0.0, 14.0)),
+        );
+
+        sprite.depth = -(tile_pos.y - 2.0) / 0.5f32.asin().tan();
+
+        ctx.draw(&sprite);
+
+        let position = d3_tile_position(tile, self.world.time) + cloth_offset();
+        let transform = transform * Transform3d::from_translation(position);
+
+        self.meshes.draw(&self.banner_preview.mesh, &transform);
+    }
+
+    // writes the mesh of the structure under the cursor to the export directory
+    #[inline]
+    fn export_mesh(&self) {
+        let tile = to_iso(self.mouse_position, Vec2::splat(TILE_SIZE))
+            .round()
+            .as_i32();
+
+        let mesh = match self.world.tiles.get(&tile) {
+            Some(Tile::Grass {
+                structure: Some(structure),
+                ..
+            }) => structure.mesh(&self.props),
+            _ => None,
+        };
+
+        let mesh = match mesh {
+            Some(mesh) => mesh,
+            None => {
+                log::info!("nothing to export at {}, {}", tile.x, tile.y);
+                return;
+            }
+        };
+
+        let path = Path::new(&self.config.export.path).join(format!("{}_{}.obj", tile.x, tile.y));
+
+        match model::write_obj(mesh, &path) {
+            Ok(()) => log::info!("exported mesh to {}", path.display()),
+            Err(err) => log::error!("failed to export mesh: {}", err),
+        }
+    }
+
+    #[inline]
+    fn reload_error(&mut self, source: &'static str, err: ike::anyhow::Error) {
+        log::error!("failed to reload {}: {:#}", source, err);
+        self.reload_errors.insert(source, format!("{:#}", err));
     }
 }
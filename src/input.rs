@@ -0,0 +1,115 @@
+use ike::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{banner::BannerChange, config::Config, inventory::HOTBAR_SIZE};
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct ButtonInput {
+    pub down: bool,
+    pub pressed: bool,
+    pub released: bool,
+}
+
+impl ButtonInput {
+    #[inline]
+    pub fn from_ctx(ctx: &UpdateCtx, button: &MouseButton) -> Self {
+        Self {
+            down: ctx.mouse_input.down(button),
+            pressed: ctx.mouse_input.pressed(button),
+            released: ctx.mouse_input.released(button),
+        }
+    }
+
+    #[inline]
+    pub fn from_key(ctx: &UpdateCtx, key: &Key) -> Self {
+        Self {
+            down: ctx.key_input.down(key),
+            pressed: ctx.key_input.pressed(key),
+            released: ctx.key_input.released(key),
+        }
+    }
+}
+
+// a bound key that is held down or was released this step
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct KeyInput {
+    pub key: Key,
+    pub state: ButtonInput,
+}
+
+// everything the simulation needs from the outside world for a single step
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Input {
+    pub delta_time: f32,
+    // mouse position in world space
+    pub mouse: Vec2,
+    pub primary: ButtonInput,
+    pub secondary: ButtonInput,
+    // hotbar slot selected with a number key this step
+    #[serde(default)]
+    pub select: Option<usize>,
+    // hotbar slot under the mouse
+    #[serde(default)]
+    pub hotbar: Option<usize>,
+    #[serde(default)]
+    pub banner: Option<BannerChange>,
+    // edges of every bound key, `select` and `banner` are read from these
+    #[serde(default)]
+    pub keys: Vec<KeyInput>,
+}
+
+impl Input {
+    #[inline]
+    pub fn from_ctx(ctx: &UpdateCtx, mouse: Vec2, hotbar: Option<usize>, cfg: &Config) -> Self {
+        let controls = &cfg.controls;
+
+        let mut keys: Vec<KeyInput> = Vec::new();
+
+        for key in controls.keys() {
+            let state = ButtonInput::from_key(ctx, key);
+
+            if (state.down || state.released) && !keys.iter().any(|input| input.key == *key) {
+                keys.push(KeyInput { key: *key, state });
+            }
+        }
+
+        let mut input = Self {
+            delta_time: ctx.delta_time,
+            mouse,
+            primary: ButtonInput::from_ctx(ctx, &controls.primary),
+            secondary: ButtonInput::from_ctx(ctx, &controls.secondary),
+            select: None,
+            hotbar,
+            banner: None,
+            keys,
+        };
+
+        input.select = controls
+            .hotbar
+            .iter()
+            .take(HOTBAR_SIZE)
+            .position(|key| input.key(key).pressed);
+
+        input.banner = if input.key(&controls.banner_pattern).pressed {
+            Some(BannerChange::Pattern)
+        } else if input.key(&controls.banner_color).pressed {
+            Some(BannerChange::Color(0))
+        } else if input.key(&controls.banner_accent).pressed {
+            Some(BannerChange::Color(1))
+        } else {
+            None
+        };
+
+        input
+    }
+
+    // state of `key` this step, keys that aren't bound are never down
+    #[inline]
+    pub fn key(&self, key: &Key) -> ButtonInput {
+        self.keys
+            .iter()
+            .find(|input| input.key == *key)
+            .map(|input| input.state)
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,261 @@
+use ike::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{assets::Assets, banner::Banner, item::ItemType, item_kind::ItemKinds};
+
+pub const HOTBAR_SIZE: usize = 9;
+
+// hotbar layout in view space, relative to the camera center at the default
+// zoom, `GameState` scales it so the hotbar keeps its size on screen
+const SLOT_SIZE: f32 = 20.0;
+const HOTBAR_Y: f32 = -128.0 + SLOT_SIZE;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Stack {
+    pub ty: ItemType,
+    pub count: u32,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Inventory {
+    pub slots: Vec<Option<Stack>>,
+    pub selected: usize,
+    // flown by poles placed from the hotbar
+    #[serde(default)]
+    pub banner: Banner,
+    // slot being dragged out of the hotbar
+    #[serde(skip)]
+    pub drag: Option<usize>,
+}
+
+impl Default for Inventory {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            slots: vec![None; HOTBAR_SIZE],
+            selected: 0,
+            banner: Banner::default(),
+            drag: None,
+        }
+    }
+}
+
+impl Inventory {
+    // what new farms start with
+    #[inline]
+    pub fn starting() -> Self {
+        let mut inventory = Self::default();
+
+        inventory.slots[0] = Some(Stack {
+            ty: ItemType::new("collector"),
+            count: 1,
+        });
+
+        inventory
+    }
+
+    // fixes up inventories loaded from older saves or with a different hotbar size
+    #[inline]
+    pub fn normalize(&mut self) {
+        self.slots.resize(HOTBAR_SIZE, None);
+        self.selected = self.selected.min(HOTBAR_SIZE - 1);
+    }
+
+    #[inline]
+    pub fn selected_stack(&self) -> Option<&Stack> {
+        self.slots.get(self.selected)?.as_ref()
+    }
+
+    #[inline]
+    pub fn selected_ty(&self) -> Option<&ItemType> {
+        Some(&self.selected_stack()?.ty)
+    }
+
+    #[inline]
+    pub fn selected_count(&self) -> u32 {
+        self.selected_stack().map_or(0, |stack| stack.count)
+    }
+
+    #[inline]
+    pub fn consume(&mut self) {
+        self.consume_count(1);
+    }
+
+    #[inline]
+    pub fn consume_count(&mut self, count: u32) {
+        let slot = &mut self.slots[self.selected];
+
+        if let Some(stack) = slot {
+            if stack.count > count {
+                stack.count -= count;
+            } else {
+                *slot = None;
+            }
+        }
+    }
+
+    #[inline]
+    pub fn can_insert(&self, ty: &ItemType, kinds: &ItemKinds) -> bool {
+        let max_stack = kinds.max_stack(ty);
+
+        self.slots.iter().any(|slot| match slot {
+            Some(stack) => stack.ty == *ty && stack.count < max_stack,
+            None => true,
+        })
+    }
+
+    // fills existing stacks of `ty` first, then empty slots, returns what didn't fit
+    #[inline]
+    pub fn insert(&mut self, ty: &ItemType, mut count: u32, kinds: &ItemKinds) -> u32 {
+        let max_stack = kinds.max_stack(ty);
+
+        for stack in self.slots.iter_mut().flatten() {
+            if count == 0 {
+                break;
+            }
+
+            if stack.ty == *ty {
+                let moved = count.min(max_stack.saturating_sub(stack.count));
+                stack.count += moved;
+                count -= moved;
+            }
+        }
+
+        for slot in &mut self.slots {
+            if count == 0 {
+                break;
+            }
+
+            if slot.is_none() {
+                let moved = count.min(max_stack);
+
+                *slot = Some(Stack {
+                    ty: ty.clone(),
+                    count: moved,
+                });
+
+                count -= moved;
+            }
+        }
+
+        count
+    }
+
+    // moves the dragged slot onto `target`, merging equal stacks and swapping different ones
+    #[inline]
+    pub fn move_slot(&mut self, from: usize, target: usize, kinds: &ItemKinds) {
+        if from == target {
+            return;
+        }
+
+        match (self.slots[from].take(), self.slots[target].take()) {
+            (Some(mut from_stack), Some(mut target_stack)) if from_stack.ty == target_stack.ty => {
+                let max_stack = kinds.max_stack(&target_stack.ty);
+                let moved = from_stack
+                    .count
+                    .min(max_stack.saturating_sub(target_stack.count));
+
+                target_stack.count += moved;
+                from_stack.count -= moved;
+
+                if from_stack.count > 0 {
+                    self.slots[from] = Some(from_stack);
+                }
+
+                self.slots[target] = Some(target_stack);
+            }
+            (from_stack, target_stack) => {
+                self.slots[from] = target_stack;
+                self.slots[target] = from_stack;
+            }
+        }
+    }
+
+    #[inline]
+    pub fn render(
+        &self,
+        ctx: &mut UpdateCtx,
+        assets: &Assets,
+        kinds: &ItemKinds,
+        origin: Vec2,
+        scale: f32,
+        mouse: Vec2,
+    ) {
+        for (i, slot) in self.slots.iter().enumerate() {
+            let position = origin + slot_position(i) * scale;
+
+            let texture = if i == self.selected {
+                &assets.slot_selected
+            } else {
+                &assets.slot
+            };
+
+            let mut transform = Transform2d::from_translation(position);
+            transform.scale = Vec2::splat(scale);
+
+            let mut sprite = Sprite::new(texture, transform);
+            sprite.depth = 450.0;
+
+            ctx.draw(&sprite);
+
+            if let Some(stack) = slot {
+                // dragged stacks are drawn at the cursor instead
+                let position = if self.drag == Some(i) { mouse } else { position };
+
+                render_stack(ctx, assets, kinds, stack, position, scale);
+            }
+        }
+    }
+}
+
+#[inline]
+fn render_stack(
+    ctx: &mut UpdateCtx,
+    assets: &Assets,
+    kinds: &ItemKinds,
+    stack: &Stack,
+    position: Vec2,
+    scale: f32,
+) {
+    let texture = match kinds.texture(&stack.ty) {
+        Some(texture) => texture,
+        None => return,
+    };
+
+    let mut transform = Transform2d::from_translation(position);
+    transform.scale = Vec2::splat(scale);
+
+    let mut sprite = Sprite::new(texture, transform);
+    sprite.depth = 460.0;
+
+    ctx.draw(&sprite);
+
+    if stack.count > 1 {
+        let mut transform =
+            Transform2d::from_translation(position + Vec2::new(4.0, -6.0) * scale);
+        transform.scale = Vec2::splat(scale);
+
+        let mut text = TextSprite::new(&assets.font, transform);
+        text.text = stack.count.to_string();
+        text.depth = 470.0;
+
+        ctx.draw(&text);
+    }
+}
+
+#[inline]
+pub fn slot_position(slot: usize) -> Vec2 {
+    let x = (slot as f32 - (HOTBAR_SIZE - 1) as f32 / 2.0) * SLOT_SIZE;
+
+    Vec2::new(x, HOTBAR_Y)
+}
+
+// hotbar slot under `point` in view space
+#[inline]
+pub fn slot_at(point: Vec2) -> Option<usize> {
+    (0..HOTBAR_SIZE).find(|&slot| {
+        let delta = (point - slot_position(slot)).abs();
+
+        delta.x <= SLOT_SIZE / 2.0 && delta.y <= SLOT_SIZE / 2.0
+    })
+}
@@ -1,5 +1,8 @@
 use ike::prelude::*;
 
+// width of a tile sprite, the top face is half as tall
+pub const TILE_SIZE: f32 = 40.0;
+
 #[inline]
 pub fn to_iso(screen: Vec2, tile_size: Vec2) -> Vec2 {
     Vec2::new(
@@ -15,3 +18,49 @@ pub fn from_iso(iso: Vec2, tile_size: Vec2) -> Vec2 {
         (iso.x + iso.y) * tile_size.y / 4.0,
     )
 }
+
+// rotation of the 3d pass, 3d points are drawn at the x and y of the rotated
+// point in world space, so meshes line up with the tile sprites
+#[inline]
+pub fn d3_rotation() -> Quat {
+    Quat::from_rotation_x(0.5f32.asin()) * Quat::from_rotation_y(std::f32::consts::FRAC_PI_4)
+}
+
+// how far `tile` has floated up at `time`
+#[inline]
+pub fn tile_bob(tile: IVec2, time: f32) -> f32 {
+    let d = tile.x as f32 + tile.y as f32;
+
+    (d * 2.0 + time * 0.5).sin()
+}
+
+// world space position of the sprite of `tile` at `time`
+#[inline]
+pub fn tile_position(tile: IVec2, time: f32) -> Vec2 {
+    from_iso(tile.as_f32(), Vec2::splat(TILE_SIZE)) + Vec2::new(0.0, tile_bob(tile, time))
+}
+
+// 3d position of `tile` at `time`, bobbing along with the tile sprite
+#[inline]
+pub fn d3_tile_position(tile: IVec2, time: f32) -> Vec3 {
+    Vec3::new(
+        tile.x as f32 * TILE_SIZE * std::f32::consts::FRAC_1_SQRT_2,
+        tile_bob(tile, time),
+        -tile.y as f32 * TILE_SIZE * std::f32::consts::FRAC_1_SQRT_2,
+    )
+}
+
+// world space point the 3d pass draws `position` at
+#[inline]
+pub fn from_d3(position: Vec3) -> Vec2 {
+    (d3_rotation() * position).truncate()
+}
+
+// 3d point drawn at world space `point` at the same depth as `position`
+#[inline]
+pub fn to_d3(point: Vec2, position: Vec3) -> Vec3 {
+    let rotation = d3_rotation();
+    let depth = (rotation * position).z;
+
+    rotation.inverse() * point.extend(depth)
+}
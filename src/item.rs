@@ -1,35 +1,57 @@
 use std::collections::HashMap;
 
 use ike::prelude::*;
-
-use crate::{assets::Assets, config::Config, game_state::GameState, iso::to_iso, tile::Tile};
-
-#[derive(PartialEq, Eq)]
-pub enum ItemType {
-    WheatSeed,
-    Wheat,
-    Pole,
-    Wood,
-    Sapling,
-}
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    assets::Assets,
+    config::Config,
+    game_state::GameState,
+    input::Input,
+    iso::{from_iso, tile_bob},
+    item_grid::{item_tile, ItemGrid},
+    item_kind::ItemKinds,
+    tile::Tile,
+};
+
+// time an item has to lie still before it merges with its neighbours
+const MERGE_DELAY: f32 = 1.0;
+// speed items slide towards the stack they're merging into
+const MERGE_SPEED: f32 = 24.0;
+
+const GRAVITY: f32 = 96.0;
+// fraction of the speed kept when bouncing off the ground or a structure
+const BOUNCE: f32 = 0.3;
+// radius of the structure in the middle of a tile that items bounce off
+const STRUCTURE_RADIUS: f32 = 6.0;
+// items that fell this far below the islands are lost
+const FALL_DEPTH: f32 = -400.0;
+
+// id of an item kind, see `ItemKinds`
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ItemType(pub String);
 
 impl ItemType {
-    pub fn texture<'a>(&self, assets: &'a Assets) -> &'a Texture {
-        match self {
-            Self::WheatSeed => &assets.wheat_seed,
-            Self::Wheat => &assets.wheat_item,
-            Self::Pole => &assets.pole_item,
-            Self::Wood => &assets.wood_item,
-            Self::Sapling => &assets.sapling_item,
-        }
+    #[inline]
+    pub fn new(id: &str) -> Self {
+        Self(String::from(id))
     }
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Item {
     pub position: Vec3,
     pub ty: ItemType,
     pub velocity: Vec3,
     pub count: u32,
+    // put down by the player, the cursor magnet leaves these alone
+    #[serde(default)]
+    pub dropped: bool,
+    // time spent lying on the ground
+    #[serde(skip)]
+    pub rest: f32,
 }
 
 pub struct Drag {
@@ -41,36 +63,61 @@ pub struct Drag {
 pub struct Items {
     pub drag: Option<Drag>,
     pub items: HashMap<Id<Item>, Item>,
+    pub grid: ItemGrid,
 }
 
 impl Items {
+    // spawns an item that pops out in a random direction, `rng` is the stream of
+    // whatever spawned it so drops don't depend on the order tiles are visited in
+    #[inline]
+    pub fn spawn(
+        &mut self,
+        ty: ItemType,
+        position: Vec2,
+        count: u32,
+        rng: &mut impl Rng,
+    ) -> Id<Item> {
+        let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+        let speed = rng.gen_range(12.0..28.0);
+        let velocity = (Vec2::new(angle.cos(), angle.sin()) * speed).extend(32.0);
+
+        self.insert(ty, position.extend(8.0), velocity, count, false)
+    }
+
+    // puts down an item held by the player
     #[inline]
-    pub fn spawn(&mut self, ty: ItemType, position: Vec2, count: u32) -> Id<Item> {
+    pub fn drop_at(&mut self, ty: ItemType, position: Vec2, count: u32) -> Id<Item> {
+        self.insert(ty, position.extend(0.0), Vec3::ZERO, count, true)
+    }
+
+    #[inline]
+    fn insert(
+        &mut self,
+        ty: ItemType,
+        position: Vec3,
+        velocity: Vec3,
+        count: u32,
+        dropped: bool,
+    ) -> Id<Item> {
         let id = Id::new();
 
         let item = Item {
-            position: (position - Vec2::Y * 4.0).extend(8.0),
+            position: position - Vec3::Y * 4.0,
             ty,
-            velocity: Vec3::Z * -32.0,
+            velocity,
             count,
+            dropped,
+            rest: 0.0,
         };
 
         self.items.insert(id, item);
         id
     }
 
+    // removes the dragged item from the world
     #[inline]
-    pub fn consume(&mut self) {
-        if let Some(ref drag) = self.drag {
-            let item = self.items.get_mut(&drag.id).unwrap();
-
-            if item.count > 1 {
-                item.count -= 1;
-            } else {
-                self.items.remove(&drag.id);
-                self.drag = None;
-            }
-        }
+    pub fn take_drag(&mut self) -> Option<Item> {
+        self.items.remove(&self.drag.take()?.id)
     }
 
     #[inline]
@@ -83,92 +130,317 @@ impl Items {
         self.items.get_mut(&self.drag.as_ref()?.id)
     }
 
+    // pulls items within `radius` of `target` towards it, removing and returning the ones
+    // that reach it, sorted by position so collecting them doesn't depend on map order
+    #[inline]
+    pub fn attract(
+        &mut self,
+        target: Vec2,
+        radius: f32,
+        speed: f32,
+        delta_time: f32,
+        mut filter: impl FnMut(&Item) -> bool,
+    ) -> Vec<Item> {
+        let drag = self.drag.as_ref().map(|drag| drag.id);
+        let mut reached = Vec::new();
+
+        let nearby = self
+            .grid
+            .query(target - Vec2::splat(radius), target + Vec2::splat(radius));
+
+        for id in nearby {
+            let item = match self.items.get_mut(&id) {
+                Some(item) => item,
+                None => continue,
+            };
+
+            if Some(id) == drag || !filter(item) {
+                continue;
+            }
+
+            let delta = target - item.position.truncate();
+            let distance = delta.length();
+
+            if distance > radius {
+                continue;
+            }
+
+            let step = speed * delta_time;
+
+            if distance <= step.max(2.0) {
+                reached.push(id);
+            } else {
+                item.position += (delta / distance * step).extend(0.0);
+            }
+        }
+
+        let mut reached: Vec<_> = reached
+            .into_iter()
+            .filter_map(|id| self.items.remove(&id))
+            .collect();
+
+        // total_cmp so a stray NaN position can't panic the sort
+        reached.sort_by(|a, b| {
+            a.position
+                .y
+                .total_cmp(&b.position.y)
+                .then(a.position.x.total_cmp(&b.position.x))
+        });
+
+        reached
+    }
+
     #[inline]
     pub fn update(
         &mut self,
-        ctx: &mut UpdateCtx,
+        input: &Input,
         tiles: &HashMap<IVec2, Tile>,
-        mouse: Vec2,
+        kinds: &ItemKinds,
         time: f32,
         cfg: &Config,
     ) {
-        if !ctx.mouse_input.down(&cfg.controls.primary) {
-            self.drag = None;
+        let mouse = input.mouse;
+
+        if !input.primary.down {
+            if let Some(drag) = self.drag.take() {
+                if let Some(item) = self.items.get_mut(&drag.id) {
+                    item.dropped = true;
+                }
+            }
         }
 
-        let mut despawn = Vec::new();
+        let mut lost = Vec::new();
+
+        self.grid.clear();
 
         for (id, item) in &mut self.items {
-            let iso = to_iso(item.position.truncate(), Vec2::splat(40.0))
-                .round()
-                .as_i32();
+            let iso = item_tile(item.position);
+            self.grid.insert(iso, *id);
+
+            item.velocity.z -= GRAVITY * input.delta_time;
 
-            item.velocity.z -= 64.0 * ctx.delta_time;
+            let tile = tiles.get(&iso);
 
-            if tiles.contains_key(&iso) {
-                let d = iso.x as f32 + iso.y as f32;
+            let offset = tile_bob(iso, time);
 
-                let offset = (d * 2.0 + time * 0.5).sin();
+            // items that already slid off an edge keep falling past the island
+            let grounded = tile.is_some() && item.position.z > offset - 4.0;
 
+            if grounded {
                 if item.position.z <= offset {
                     item.position.z = offset;
-                    item.velocity.z *= -0.1;
+
+                    if item.velocity.z < 0.0 {
+                        item.velocity.z *= -BOUNCE;
+                    }
+                }
+
+                // small bounces still count as resting
+                if item.position.z <= offset + 0.5 {
+                    let friction = (-cfg.items.friction * input.delta_time).exp();
+                    item.velocity.x *= friction;
+                    item.velocity.y *= friction;
+
+                    item.rest += input.delta_time;
+                } else {
+                    item.rest = 0.0;
                 }
             } else {
-                if item.velocity.z < -512.0 {
-                    despawn.push(*id);
+                item.rest = 0.0;
+            }
+
+            if let Some(Tile::Grass {
+                structure: Some(_), ..
+            }) = tile
+            {
+                if item.position.z < 24.0 {
+                    let center = from_iso(iso.as_f32(), Vec2::splat(40.0));
+                    collide_structure(item, center);
                 }
             }
 
-            item.position += item.velocity * ctx.delta_time;
+            item.position += item.velocity * input.delta_time;
+
+            if item.position.z < FALL_DEPTH {
+                lost.push(*id);
+            }
         }
 
-        for id in despawn {
-            self.items.remove(&id);
+        for id in lost {
+            if cfg.items.respawn_lost {
+                if let Some(item) = self.items.get_mut(&id) {
+                    respawn(item, tiles);
+                }
+            } else {
+                self.items.remove(&id);
+            }
         }
 
+        self.merge_resting(kinds, input.delta_time);
+
         if let Some(ref drag) = self.drag {
             let item = self.items.get_mut(&drag.id).unwrap();
             item.position = (mouse + drag.offset).extend(0.0);
-            item.velocity.z = 0.0;
+            item.velocity = Vec3::ZERO;
 
             let mut merge = Vec::new();
             let drag_item = self.items.get(&drag.id).unwrap();
+            let max_stack = kinds.max_stack(&drag_item.ty);
 
-            for (id, item) in &self.items {
-                if *id == drag.id {
-                    continue;
-                }
+            let position = drag_item.position.truncate();
+
+            for id in self
+                .grid
+                .query(position - Vec2::splat(8.0), position + Vec2::splat(8.0))
+            {
+                let item = match self.items.get(&id) {
+                    Some(item) if id != drag.id => item,
+                    _ => continue,
+                };
+
+                let distance = drag_item.position.distance(item.position);
 
-                if drag_item.position.distance(item.position) < 8.0 && drag_item.ty == item.ty {
-                    merge.push(*id);
+                if distance < 8.0 && drag_item.ty == item.ty {
+                    merge.push((distance, id));
                 }
             }
 
-            for id in merge {
-                let item = self.items.remove(&id).unwrap();
+            // merge closest first so the result doesn't depend on map order
+            merge.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+            for (_, id) in merge {
+                let space = max_stack.saturating_sub(self.items[&drag.id].count);
+
+                if space == 0 {
+                    break;
+                }
+
+                let item = self.items.get_mut(&id).unwrap();
+                let moved = item.count.min(space);
+                item.count -= moved;
+
+                if item.count == 0 {
+                    self.items.remove(&id);
+                }
 
-                self.items.get_mut(&drag.id).unwrap().count += item.count;
+                self.items.get_mut(&drag.id).unwrap().count += moved;
             }
-        } else {
-            for (id, item) in &mut self.items {
+        } else if input.primary.pressed && input.hotbar.is_none() {
+            // pick the front most item under the mouse, items are hit above their
+            // position so look further down for them
+            let mut hovered: Option<(Id<Item>, Vec3)> = None;
+
+            let nearby = self
+                .grid
+                .query(mouse - Vec2::new(8.0, 32.0), mouse + Vec2::new(8.0, 8.0));
+
+            for id in nearby {
+                let item = match self.items.get(&id) {
+                    Some(item) => item,
+                    None => continue,
+                };
+
                 if mouse.x >= item.position.x - 8.0
                     && mouse.x <= item.position.x + 8.0
                     && mouse.y >= item.position.y + item.position.z
                     && mouse.y <= item.position.y + 16.0 + item.position.z
-                    && ctx.mouse_input.pressed(&cfg.controls.primary)
                 {
-                    item.velocity.z = item.velocity.z.max(0.0);
-
-                    let offset = if cfg.controls.item_offset {
-                        item.position.truncate() - mouse
-                    } else {
-                        Vec2::Y * -8.0
+                    let front = match hovered {
+                        Some((_, position)) => {
+                            (item.position.y, item.position.x) < (position.y, position.x)
+                        }
+                        None => true,
                     };
 
-                    self.drag = Some(Drag { id: *id, offset });
+                    if front {
+                        hovered = Some((id, item.position));
+                    }
                 }
             }
+
+            if let Some((id, _)) = hovered {
+                let item = self.items.get_mut(&id).unwrap();
+
+                item.velocity.z = item.velocity.z.max(0.0);
+
+                let offset = if cfg.controls.item_offset {
+                    item.position.truncate() - mouse
+                } else {
+                    Vec2::Y * -8.0
+                };
+
+                self.drag = Some(Drag { id, offset });
+            }
+        }
+    }
+
+    // resting items of the same type on a tile slide into the biggest stack that has room
+    #[inline]
+    fn merge_resting(&mut self, kinds: &ItemKinds, delta_time: f32) {
+        let drag = self.drag.as_ref().map(|drag| drag.id);
+
+        for cell in self.grid.cells() {
+            let mut resting: Vec<_> = cell
+                .iter()
+                .filter(|id| Some(**id) != drag)
+                .filter_map(|id| Some((*id, self.items.get(id)?)))
+                .filter(|(_, item)| {
+                    item.rest >= MERGE_DELAY && item.count < kinds.max_stack(&item.ty)
+                })
+                .map(|(id, item)| (id, item.ty.clone(), item.count, item.position))
+                .collect();
+
+            if resting.len() < 2 {
+                continue;
+            }
+
+            // biggest stack of each type first, ties broken by position so the
+            // result doesn't depend on map order
+            resting.sort_by(|a, b| {
+                a.1.cmp(&b.1)
+                    .then(b.2.cmp(&a.2))
+                    .then(a.3.y.total_cmp(&b.3.y))
+                    .then(a.3.x.total_cmp(&b.3.x))
+            });
+
+            let mut target: Option<(Id<Item>, &ItemType, Vec3)> = None;
+
+            for (id, ty, _, position) in &resting {
+                let (target_id, target_position) = match target {
+                    Some((target_id, target_ty, target_position)) if target_ty == ty => {
+                        (target_id, target_position)
+                    }
+                    _ => {
+                        target = Some((*id, ty, *position));
+                        continue;
+                    }
+                };
+
+                let delta = (target_position - *position).truncate();
+                let distance = delta.length();
+                let step = MERGE_SPEED * delta_time;
+
+                if distance > step.max(1.0) {
+                    let item = self.items.get_mut(id).unwrap();
+                    item.position += (delta / distance * step).extend(0.0);
+
+                    continue;
+                }
+
+                let space = kinds
+                    .max_stack(ty)
+                    .saturating_sub(self.items[&target_id].count);
+
+                let item = self.items.get_mut(id).unwrap();
+                let moved = item.count.min(space);
+                item.count -= moved;
+
+                if item.count == 0 {
+                    self.items.remove(id);
+                }
+
+                self.items.get_mut(&target_id).unwrap().count += moved;
+            }
         }
     }
 
@@ -177,9 +449,15 @@ impl Items {
         &self,
         ctx: &mut UpdateCtx,
         assets: &mut Assets,
+        kinds: &ItemKinds,
         config: &Config,
     ) {
         for (_id, item) in &self.items {
+            let texture = match kinds.texture(&item.ty) {
+                Some(texture) => texture,
+                None => continue,
+            };
+
             if item.count > 1 || config.graphics.always_show_stack_size {
                 let mut text = TextSprite::new(
                     &assets.font,
@@ -193,8 +471,6 @@ impl Items {
                 ctx.draw(&text);
             }
 
-            let texture = item.ty.texture(assets);
-
             let position = item.position.truncate() + Vec2::Y * item.position.z;
 
             let mut sprite = Sprite::new(
@@ -208,3 +484,167 @@ impl Items {
         }
     }
 }
+
+// pushes an item out of the structure standing at `center` and bounces it off
+#[inline]
+fn collide_structure(item: &mut Item, center: Vec2) {
+    let delta = item.position.truncate() - center;
+    let distance = delta.length();
+
+    if distance >= STRUCTURE_RADIUS {
+        return;
+    }
+
+    let normal = if distance > 0.0 {
+        delta / distance
+    } else {
+        Vec2::Y
+    };
+
+    let position = center + normal * STRUCTURE_RADIUS;
+    item.position.x = position.x;
+    item.position.y = position.y;
+
+    let velocity = item.velocity.truncate();
+    let along = velocity.dot(normal);
+
+    if along < 0.0 {
+        let velocity = velocity - normal * along * (1.0 + BOUNCE);
+        item.velocity.x = velocity.x;
+        item.velocity.y = velocity.y;
+    }
+}
+
+// drops a lost item back onto the tile closest to where it fell
+#[inline]
+fn respawn(item: &mut Item, tiles: &HashMap<IVec2, Tile>) {
+    let position = item.position.truncate();
+
+    let closest = tiles
+        .keys()
+        .map(|tile| (from_iso(tile.as_f32(), Vec2::splat(40.0)), *tile))
+        .min_by(|(a, a_tile), (b, b_tile)| {
+            a.distance_squared(position)
+                .total_cmp(&b.distance_squared(position))
+                .then((a_tile.x, a_tile.y).cmp(&(b_tile.x, b_tile.y)))
+        });
+
+    if let Some((center, _)) = closest {
+        item.position = center.extend(64.0);
+        item.velocity = Vec3::ZERO;
+        item.rest = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    const STEPS: u32 = 10;
+
+    // a square island `radius` tiles out from the middle
+    fn island(radius: i32) -> HashMap<IVec2, Tile> {
+        let mut tiles = HashMap::new();
+
+        for x in -radius..=radius {
+            for y in -radius..=radius {
+                tiles.insert(IVec2::new(x, y), Tile::grass_plain());
+            }
+        }
+
+        tiles
+    }
+
+    fn scatter(count: usize, radius: i32) -> Items {
+        let mut items = Items::default();
+        let mut rng = StdRng::seed_from_u64(count as u64);
+
+        let kinds = ["wheat", "wheat_seed", "wood", "apple"];
+
+        for i in 0..count {
+            let tile = Vec2::new(
+                rng.gen_range(-radius as f32..=radius as f32),
+                rng.gen_range(-radius as f32..=radius as f32),
+            );
+
+            let position = from_iso(tile, Vec2::splat(40.0));
+            items.drop_at(ItemType::new(kinds[i % kinds.len()]), position, 1);
+        }
+
+        items
+    }
+
+    // run with `cargo test --release -- --ignored --nocapture item_scaling`
+    #[test]
+    #[ignore]
+    fn item_scaling() {
+        let cfg = Config::default();
+        let kinds = ItemKinds::load_headless(&cfg.items.path).unwrap();
+        let tiles = island(20);
+
+        let input = Input {
+            delta_time: 1.0 / 60.0,
+            ..Default::default()
+        };
+
+        for &count in &[100, 1_000, 10_000] {
+            let mut items = scatter(count, 20);
+
+            let start = Instant::now();
+
+            for _ in 0..STEPS {
+                items.update(&input, &tiles, &kinds, 0.0, &cfg);
+            }
+
+            let update = start.elapsed() / STEPS;
+
+            // hit test around every item, once with the grid and once against every item
+            let positions: Vec<Vec2> = items
+                .items
+                .values()
+                .map(|item| item.position.truncate())
+                .collect();
+
+            let start = Instant::now();
+            let mut grid_hits = 0;
+
+            for &position in &positions {
+                grid_hits += items
+                    .grid
+                    .query(position - Vec2::splat(8.0), position + Vec2::splat(8.0))
+                    .count();
+            }
+
+            let grid = start.elapsed();
+
+            let start = Instant::now();
+            let mut naive_hits = 0;
+
+            for &position in &positions {
+                naive_hits += positions
+                    .iter()
+                    .filter(|other| (**other - position).abs().max_element() <= 8.0)
+                    .count();
+            }
+
+            let naive = start.elapsed();
+
+            println!(
+                "{:>6} items: update {:?}, grid query {:?}, naive scan {:?}",
+                count, update, grid, naive
+            );
+
+            // the grid returns whole tiles, so everything in range and then some
+            assert!(grid_hits >= naive_hits);
+
+            if count == 10_000 {
+                assert!(grid < naive);
+                assert!(update < Duration::from_millis(16));
+            }
+        }
+    }
+}
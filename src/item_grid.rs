@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+
+use ike::prelude::*;
+
+use crate::{iso::to_iso, item::Item};
+
+// tile an item at `position` is over
+#[inline]
+pub fn item_tile(position: Vec3) -> IVec2 {
+    to_iso(position.truncate(), Vec2::splat(40.0))
+        .round()
+        .as_i32()
+}
+
+// item ids bucketed by the tile they're over, rebuilt every step by `Items::update`,
+// ids of items removed since the last rebuild can still be returned
+#[derive(Default)]
+pub struct ItemGrid {
+    cells: HashMap<IVec2, Vec<Id<Item>>>,
+}
+
+impl ItemGrid {
+    #[inline]
+    pub fn clear(&mut self) {
+        // keep the allocations around, the same tiles are usually filled again
+        for cell in self.cells.values_mut() {
+            cell.clear();
+        }
+    }
+
+    #[inline]
+    pub fn insert(&mut self, tile: IVec2, id: Id<Item>) {
+        self.cells.entry(tile).or_default().push(id);
+    }
+
+    #[inline]
+    pub fn cell(&self, tile: IVec2) -> &[Id<Item>] {
+        self.cells.get(&tile).map_or(&[], |cell| cell.as_slice())
+    }
+
+    #[inline]
+    pub fn cells(&self) -> impl Iterator<Item = &[Id<Item>]> {
+        self.cells
+            .values()
+            .filter(|cell| cell.len() > 1)
+            .map(|cell| cell.as_slice())
+    }
+
+    // ids in every tile overlapping the world space rectangle from `min` to `max`
+    #[inline]
+    pub fn query(&self, min: Vec2, max: Vec2) -> impl Iterator<Item = Id<Item>> + '_ {
+        let corners = [
+            to_iso(min, Vec2::splat(40.0)),
+            to_iso(max, Vec2::splat(40.0)),
+            to_iso(Vec2::new(min.x, max.y), Vec2::splat(40.0)),
+            to_iso(Vec2::new(max.x, min.y), Vec2::splat(40.0)),
+        ];
+
+        let iso_min = corners.iter().fold(corners[0], |a, b| a.min(*b));
+        let iso_max = corners.iter().fold(corners[0], |a, b| a.max(*b));
+
+        let tile_min = iso_min.round().as_i32();
+        let tile_max = iso_max.round().as_i32();
+
+        (tile_min.x..=tile_max.x)
+            .flat_map(move |x| (tile_min.y..=tile_max.y).map(move |y| IVec2::new(x, y)))
+            .flat_map(move |tile| self.cell(tile).iter().copied())
+    }
+}
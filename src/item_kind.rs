@@ -0,0 +1,129 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use ike::prelude::*;
+use serde::Deserialize;
+
+use crate::{item::ItemType, tile::StructureKind};
+
+#[inline]
+fn default_max_stack() -> u32 {
+    99
+}
+
+// what happens when the item is held over a tile and secondary is pressed
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum UseAction {
+    None,
+    // plants the crop that lists this item as its seed on farmed tiles
+    PlantCrop,
+    PlaceStructure { structure: StructureKind },
+}
+
+impl Default for UseAction {
+    #[inline]
+    fn default() -> Self {
+        Self::None
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ItemDef {
+    pub name: String,
+    pub sprite: String,
+    #[serde(default = "default_max_stack")]
+    pub max_stack: u32,
+    #[serde(default, rename = "use")]
+    pub on_use: UseAction,
+}
+
+pub struct ItemKind {
+    pub id: ItemType,
+    pub def: ItemDef,
+    // not loaded when running headless
+    pub texture: Option<Texture>,
+}
+
+impl ItemKind {
+    #[inline]
+    pub fn load(id: ItemType, path: &Path, textures: bool) -> ike::anyhow::Result<Self> {
+        let def: ItemDef = toml::from_str(&fs::read_to_string(path)?)?;
+
+        if def.max_stack == 0 {
+            ike::anyhow::bail!("item '{}' has a max stack of 0", id.0);
+        }
+
+        let texture = if textures {
+            Some(Texture::load(&def.sprite)?)
+        } else {
+            None
+        };
+
+        Ok(Self { id, def, texture })
+    }
+}
+
+#[derive(Default)]
+pub struct ItemKinds {
+    pub kinds: HashMap<ItemType, ItemKind>,
+}
+
+impl ItemKinds {
+    // loads every `*.toml` file in `path`, the file name is used as the item id
+    #[inline]
+    pub fn load(path: impl AsRef<Path>) -> ike::anyhow::Result<Self> {
+        Self::load_with(path, true)
+    }
+
+    // loads the definitions without their sprites, for simulating without a window
+    #[inline]
+    pub fn load_headless(path: impl AsRef<Path>) -> ike::anyhow::Result<Self> {
+        Self::load_with(path, false)
+    }
+
+    #[inline]
+    fn load_with(path: impl AsRef<Path>, textures: bool) -> ike::anyhow::Result<Self> {
+        let mut kinds = Self::default();
+
+        for entry in fs::read_dir(path)? {
+            let path = entry?.path();
+
+            if path.extension().map_or(true, |ext| ext != "toml") {
+                continue;
+            }
+
+            let id = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(id) => ItemType::new(id),
+                None => continue,
+            };
+
+            let kind = ItemKind::load(id.clone(), &path, textures)
+                .map_err(|err| err.context(format!("failed to load item '{}'", id.0)))?;
+
+            kinds.kinds.insert(id, kind);
+        }
+
+        Ok(kinds)
+    }
+
+    #[inline]
+    pub fn get(&self, ty: &ItemType) -> Option<&ItemKind> {
+        self.kinds.get(ty)
+    }
+
+    #[inline]
+    pub fn texture(&self, ty: &ItemType) -> Option<&Texture> {
+        self.get(ty)?.texture.as_ref()
+    }
+
+    #[inline]
+    pub fn max_stack(&self, ty: &ItemType) -> u32 {
+        self.get(ty).map_or(u32::MAX, |kind| kind.def.max_stack)
+    }
+
+    #[inline]
+    pub fn use_action(&self, ty: &ItemType) -> UseAction {
+        self.get(ty).map_or(UseAction::None, |kind| kind.def.on_use)
+    }
+}
@@ -2,19 +2,38 @@
 
 mod assets;
 mod audio;
+mod banner;
 mod cloth;
 mod config;
+mod crop;
+mod daylight;
+mod expansion;
 mod game_state;
+mod input;
+mod inventory;
 mod iso;
 mod item;
+mod item_grid;
+mod item_kind;
+mod model;
+mod prop;
 mod render;
+mod replay;
+mod rng;
+mod save;
+mod species;
 mod tile;
 mod tree;
+mod tree_gen;
+mod watch;
+mod wind;
+mod world;
 
 use game_state::GameState;
-use ike::{d2::render::SpriteNode2d, d3::D3Node, prelude::*};
-use render::{D3Pass, RenderNode};
+use ike::{d2::render::SpriteNode2d, prelude::*};
+use render::{D3Pass, MeshNode, RenderNode, ShadeNode, UiNode, UiPass};
 
+// covered every frame by the sky `RenderNode` draws behind the 3d pass
 const CLEAR_COLOR: Color = Color::rgb(123.0 / 255.0, 216.0 / 255.0, 213.0 / 255.0);
 
 fn main() {
@@ -27,10 +46,17 @@ fn main() {
 
     let mut d3_pass = Pass::new(D3Pass::default());
 
-    d3_pass.push(D3Node::default());
+    d3_pass.push(MeshNode::default());
 
     app.renderer.push(d3_pass);
 
+    // the ui gets its own buffer, `UiNode` lays it over the farm after the daylight shade
+    let mut ui_pass = Pass::new(UiPass::default());
+
+    ui_pass.push(SpriteNode2d::new());
+
+    app.renderer.push(ui_pass);
+
     let mut main_pass = MainPass::default();
 
     main_pass.clear_color = CLEAR_COLOR;
@@ -40,6 +66,8 @@ fn main() {
 
     main_pass.push(RenderNode::default());
     main_pass.push(SpriteNode2d::new());
+    main_pass.push(ShadeNode::default());
+    main_pass.push(UiNode::default());
 
     #[cfg(debug_assertions)]
     main_pass.push(DebugNode::default());
@@ -0,0 +1,86 @@
+use std::{
+    fmt::Write,
+    fs::{self, File},
+    io::BufReader,
+    path::Path,
+};
+
+use ike::{d3::Indices, prelude::*};
+use obj::{load_obj, Obj, Position};
+
+// writes `mesh` as a wavefront obj, vertex colors are written after the
+// positions which blender reads as a color attribute
+#[inline]
+pub fn write_obj(mesh: &Mesh, path: impl AsRef<Path>) -> ike::anyhow::Result<()> {
+    let path = path.as_ref();
+    let mut obj = String::new();
+
+    for vertex in mesh.vertices.iter() {
+        let position = vertex.position;
+        let color = vertex.color;
+
+        writeln!(
+            obj,
+            "v {} {} {} {} {} {}",
+            position.x, position.y, position.z, color.r, color.g, color.b
+        )?;
+    }
+
+    for vertex in mesh.vertices.iter() {
+        writeln!(obj, "vt {} {}", vertex.uv.x, vertex.uv.y)?;
+    }
+
+    for vertex in mesh.vertices.iter() {
+        let normal = vertex.normal;
+
+        writeln!(obj, "vn {} {} {}", normal.x, normal.y, normal.z)?;
+    }
+
+    // obj indices start at 1
+    for face in mesh.indices.chunks(3) {
+        if let [a, b, c] = *face {
+            let (a, b, c) = (a + 1, b + 1, c + 1);
+
+            writeln!(obj, "f {0}/{0}/{0} {1}/{1}/{1} {2}/{2}/{2}", a, b, c)?;
+        }
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    fs::write(path, obj)?;
+
+    Ok(())
+}
+
+// reads the triangles of a wavefront obj, models carry no colors so every
+// vertex gets `color`, normals are recalculated
+#[inline]
+pub fn read_obj(path: impl AsRef<Path>, scale: f32, color: Color) -> ike::anyhow::Result<Mesh> {
+    let input = BufReader::new(File::open(path)?);
+    let obj: Obj<Position, u32> = load_obj(input)?;
+
+    let mut mesh = Mesh::default();
+
+    for vertex in obj.vertices {
+        mesh.vertices.push(Vertex {
+            position: Vec3::from(vertex.position) * scale,
+            normal: Vec3::ZERO,
+            uv: Vec2::ZERO,
+            color,
+        });
+    }
+
+    for index in obj.indices {
+        if index as usize >= mesh.vertices.len() {
+            ike::anyhow::bail!("face uses vertex {} which doesn't exist", index + 1);
+        }
+
+        mesh.indices.push(index);
+    }
+
+    mesh.calculate_normals();
+
+    Ok(mesh)
+}
@@ -0,0 +1,88 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use ike::prelude::*;
+use serde::Deserialize;
+
+use crate::{item::ItemType, model, species::color};
+
+#[inline]
+fn default_scale() -> f32 {
+    1.0
+}
+
+#[inline]
+fn default_color() -> [u8; 3] {
+    [255, 255, 255]
+}
+
+// a hand made model placed on tiles by the item with the same id
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PropDef {
+    // `.obj` file, relative to the game directory
+    pub model: String,
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+    #[serde(default = "default_color")]
+    pub color: [u8; 3],
+}
+
+pub struct Prop {
+    pub id: ItemType,
+    pub def: PropDef,
+    pub mesh: Mesh,
+}
+
+impl Prop {
+    #[inline]
+    pub fn load(id: ItemType, path: &Path) -> ike::anyhow::Result<Self> {
+        let def: PropDef = toml::from_str(&fs::read_to_string(path)?)?;
+
+        if def.scale <= 0.0 {
+            ike::anyhow::bail!("prop '{}' scale must be positive", id.0);
+        }
+
+        let mesh = model::read_obj(&def.model, def.scale, color(def.color))
+            .map_err(|err| err.context(format!("failed to read model '{}'", def.model)))?;
+
+        Ok(Self { id, def, mesh })
+    }
+}
+
+#[derive(Default)]
+pub struct Props {
+    pub props: BTreeMap<ItemType, Prop>,
+}
+
+impl Props {
+    // loads every `*.toml` file in `path`, the file name is the id of the item placing it
+    #[inline]
+    pub fn load(path: impl AsRef<Path>) -> ike::anyhow::Result<Self> {
+        let mut props = Self::default();
+
+        for entry in fs::read_dir(path)? {
+            let path = entry?.path();
+
+            if path.extension().map_or(true, |ext| ext != "toml") {
+                continue;
+            }
+
+            let id = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(id) => ItemType::new(id),
+                None => continue,
+            };
+
+            let prop = Prop::load(id.clone(), &path)
+                .map_err(|err| err.context(format!("failed to load prop '{}'", id.0)))?;
+
+            props.props.insert(id, prop);
+        }
+
+        Ok(props)
+    }
+
+    #[inline]
+    pub fn get(&self, item: &ItemType) -> Option<&Prop> {
+        self.props.get(item)
+    }
+}
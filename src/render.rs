@@ -1,9 +1,82 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, ops::Range};
 
 use ike::prelude::*;
 
 use crate::game_state::GameState;
 
+// a single color passed to a shader
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct ColorUniform {
+    color: [f32; 4],
+}
+
+impl ColorUniform {
+    #[inline]
+    fn new(color: Vec3) -> Self {
+        Self {
+            color: [color.x, color.y, color.z, 1.0],
+        }
+    }
+}
+
+// the ui is laid out around this point, far away from the farm, and only `UiPass` looks at it
+pub const UI_ORIGIN: Vec2 = Vec2::new(0.0, 100_000.0);
+
+#[inline]
+fn uniform_buffer<T: bytemuck::Pod>(render_ctx: &RenderCtx) -> wgpu::Buffer {
+    render_ctx.device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: std::mem::size_of::<T>() as u64,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+#[inline]
+fn uniform_layout_entry(
+    binding: u32,
+    visibility: wgpu::ShaderStages,
+) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        visibility,
+        count: None,
+    }
+}
+
+// writes `data` to `buffer`, replacing it with a larger one when it doesn't fit
+#[inline]
+fn upload(
+    render_ctx: &RenderCtx,
+    buffer: &mut Option<wgpu::Buffer>,
+    capacity: &mut u64,
+    usage: wgpu::BufferUsages,
+    data: &[u8],
+) {
+    let size = data.len() as u64;
+
+    if buffer.is_none() || *capacity < size {
+        *capacity = size.next_power_of_two();
+
+        *buffer = Some(render_ctx.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: *capacity,
+            usage: usage | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        }));
+    }
+
+    render_ctx
+        .queue
+        .write_buffer(buffer.as_ref().unwrap(), 0, data);
+}
+
 #[derive(Default)]
 pub struct D3Pass {
     color: Option<wgpu::TextureView>,
@@ -42,31 +115,369 @@ impl RenderPass<GameState> for D3Pass {
         self.color = Some(color.create_view(&Default::default()));
         self.depth = Some(depth.create_view(&Default::default()));
 
-        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: None,
-            color_attachments: &[wgpu::RenderPassColorAttachment {
-                view: self.color.as_ref().unwrap(),
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
-                    store: true,
+        begin_buffer_pass(encoder, self.color.as_ref().unwrap(), self.depth.as_ref().unwrap())
+    }
+}
+
+// clears a frame buffer to transparent and starts drawing into it
+#[inline]
+fn begin_buffer_pass<'a>(
+    encoder: &'a mut wgpu::CommandEncoder,
+    color: &'a wgpu::TextureView,
+    depth: &'a wgpu::TextureView,
+) -> wgpu::RenderPass<'a> {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: None,
+        color_attachments: &[wgpu::RenderPassColorAttachment {
+            view: color,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                store: true,
+            },
+        }],
+        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+            view: depth,
+            depth_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(1.0),
+                store: true,
+            }),
+            stencil_ops: None,
+        }),
+    })
+}
+
+// draws the sprites seen by `GameState::ui_camera` into `GameState::ui_buffer`, the farm is
+// drawn far away from `UI_ORIGIN` so none of it ends up in here
+#[derive(Default)]
+pub struct UiPass {
+    color: Option<wgpu::TextureView>,
+    depth: Option<wgpu::TextureView>,
+}
+
+impl RenderPass<GameState> for UiPass {
+    fn run<'a>(
+        &'a mut self,
+        encoder: &'a mut wgpu::CommandEncoder,
+        ctx: &RenderCtx,
+        view: &'a View,
+        data: &mut PassData,
+        state: &mut GameState,
+    ) -> wgpu::RenderPass<'a> {
+        state.ui_buffer.descriptor.width = view.width;
+        state.ui_buffer.descriptor.height = view.height;
+        state.ui_buffer.descriptor.usage |= wgpu::TextureUsages::TEXTURE_BINDING;
+
+        data.insert(SampleCount(1));
+        data.insert(TargetFormat(state.ui_buffer.descriptor.format));
+        data.insert(TargetSize {
+            width: view.width,
+            height: view.height,
+        });
+        data.insert(state.ui_camera.camera());
+
+        let (color, depth) = state.ui_buffer.color_depth(ctx);
+
+        self.color = Some(color.create_view(&Default::default()));
+        self.depth = Some(depth.create_view(&Default::default()));
+
+        begin_buffer_pass(encoder, self.color.as_ref().unwrap(), self.depth.as_ref().unwrap())
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct MeshVertex {
+    position: [f32; 3],
+    normal: [f32; 3],
+    uv: [f32; 2],
+    color: [f32; 4],
+}
+
+const VERTEX_ATTRIBUTES: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![
+    0 => Float32x3,
+    1 => Float32x3,
+    2 => Float32x2,
+    3 => Float32x4,
+];
+
+// the transform of a mesh, one per draw
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct MeshInstance {
+    transform: [[f32; 4]; 4],
+}
+
+const INSTANCE_ATTRIBUTES: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![
+    4 => Float32x4,
+    5 => Float32x4,
+    6 => Float32x4,
+    7 => Float32x4,
+];
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct MeshUniforms {
+    view_proj: [[f32; 4]; 4],
+    // direction towards the sun or moon in xyz, and its strength in w
+    light: [f32; 4],
+}
+
+// the meshes drawn by `MeshNode` this frame, filled by `GameState::update`
+#[derive(Default)]
+pub struct MeshBatch {
+    vertices: Vec<MeshVertex>,
+    indices: Vec<u32>,
+    instances: Vec<MeshInstance>,
+    // indices of every mesh, drawn with the instance at the same position
+    draws: Vec<Range<u32>>,
+}
+
+impl MeshBatch {
+    #[inline]
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+        self.indices.clear();
+        self.instances.clear();
+        self.draws.clear();
+    }
+
+    #[inline]
+    pub fn draw(&mut self, mesh: &Mesh, transform: &Transform3d) {
+        if mesh.indices.is_empty() {
+            return;
+        }
+
+        let base = self.vertices.len() as u32;
+        let start = self.indices.len() as u32;
+
+        self.vertices
+            .extend(mesh.vertices.iter().map(|vertex| MeshVertex {
+                position: vertex.position.into(),
+                normal: vertex.normal.into(),
+                uv: vertex.uv.into(),
+                color: [vertex.color.r, vertex.color.g, vertex.color.b, vertex.color.a],
+            }));
+
+        self.indices
+            .extend(mesh.indices.iter().map(|index| base + index));
+
+        let matrix = Mat4::from_scale_rotation_translation(
+            transform.scale,
+            transform.rotation,
+            transform.translation,
+        );
+
+        self.instances.push(MeshInstance {
+            transform: matrix.to_cols_array_2d(),
+        });
+        self.draws.push(start..self.indices.len() as u32);
+    }
+}
+
+// draws the queued `MeshBatch` into the 3d pass lit by the daylight, see `shader.wgsl`
+#[derive(Default)]
+pub struct MeshNode {
+    shader_module: Option<wgpu::ShaderModule>,
+    uniform_buffer: Option<wgpu::Buffer>,
+    vertex_buffer: Option<wgpu::Buffer>,
+    vertex_capacity: u64,
+    index_buffer: Option<wgpu::Buffer>,
+    index_capacity: u64,
+    instance_buffer: Option<wgpu::Buffer>,
+    instance_capacity: u64,
+    bind_group: Option<wgpu::BindGroup>,
+    pipeline_layout: Option<wgpu::PipelineLayout>,
+    pipelines: HashMap<wgpu::TextureFormat, wgpu::RenderPipeline>,
+}
+
+impl MeshNode {
+    #[inline]
+    fn create_pipeline_layout(&mut self, render_ctx: &RenderCtx) {
+        self.shader_module = Some(
+            render_ctx
+                .device
+                .create_shader_module(&wgpu::include_wgsl!("shader.wgsl")),
+        );
+
+        let bind_group_layout =
+            render_ctx
+                .device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: None,
+                    entries: &[uniform_layout_entry(0, wgpu::ShaderStages::VERTEX_FRAGMENT)],
+                });
+
+        let uniform_buffer = uniform_buffer::<MeshUniforms>(render_ctx);
+
+        self.bind_group = Some(
+            render_ctx
+                .device
+                .create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: None,
+                    layout: &bind_group_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: uniform_buffer.as_entire_binding(),
+                    }],
+                }),
+        );
+
+        self.uniform_buffer = Some(uniform_buffer);
+
+        self.pipeline_layout = Some(render_ctx.device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        ));
+    }
+
+    #[inline]
+    fn create_pipeline(
+        &mut self,
+        render_ctx: &RenderCtx,
+        format: wgpu::TextureFormat,
+        samples: u32,
+    ) -> wgpu::RenderPipeline {
+        render_ctx
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: None,
+                layout: Some(self.pipeline_layout.as_ref().unwrap()),
+                vertex: wgpu::VertexState {
+                    module: self.shader_module.as_ref().unwrap(),
+                    entry_point: "main",
+                    buffers: &[
+                        wgpu::VertexBufferLayout {
+                            array_stride: std::mem::size_of::<MeshVertex>() as u64,
+                            step_mode: wgpu::VertexStepMode::Vertex,
+                            attributes: &VERTEX_ATTRIBUTES,
+                        },
+                        wgpu::VertexBufferLayout {
+                            array_stride: std::mem::size_of::<MeshInstance>() as u64,
+                            step_mode: wgpu::VertexStepMode::Instance,
+                            attributes: &INSTANCE_ATTRIBUTES,
+                        },
+                    ],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: self.shader_module.as_ref().unwrap(),
+                    entry_point: "main",
+                    targets: &[wgpu::ColorTargetState {
+                        format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }],
+                }),
+                // cloth is seen from both sides
+                primitive: Default::default(),
+                multisample: wgpu::MultisampleState {
+                    count: samples,
+                    ..Default::default()
                 },
-            }],
-            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                view: self.depth.as_ref().unwrap(),
-                depth_ops: Some(wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(1.0),
-                    store: true,
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: wgpu::TextureFormat::Depth24Plus,
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::LessEqual,
+                    stencil: Default::default(),
+                    bias: Default::default(),
                 }),
-                stencil_ops: None,
-            }),
-        })
+            })
+    }
+}
+
+impl PassNode<GameState> for MeshNode {
+    #[inline]
+    fn run<'a>(&'a mut self, ctx: &mut PassNodeCtx<'_, 'a>, state: &mut GameState) {
+        let batch = &state.meshes;
+
+        if batch.draws.is_empty() {
+            return;
+        }
+
+        if self.pipeline_layout.is_none() {
+            self.create_pipeline_layout(ctx.render_ctx);
+        }
+
+        let format = ctx.data.get::<TargetFormat>().unwrap().0;
+
+        if !self.pipelines.contains_key(&format) {
+            let pipeline = self.create_pipeline(
+                ctx.render_ctx,
+                format,
+                ctx.data.get::<SampleCount>().unwrap().0,
+            );
+            self.pipelines.insert(format, pipeline);
+        }
+
+        let camera = ctx.data.get::<Camera>().unwrap();
+        let daylight = &state.daylight;
+
+        let uniforms = MeshUniforms {
+            view_proj: (camera.proj * camera.view.inverse()).to_cols_array_2d(),
+            light: daylight.light_dir.extend(daylight.light).into(),
+        };
+
+        ctx.render_ctx.queue.write_buffer(
+            self.uniform_buffer.as_ref().unwrap(),
+            0,
+            bytemuck::bytes_of(&uniforms),
+        );
+
+        upload(
+            ctx.render_ctx,
+            &mut self.vertex_buffer,
+            &mut self.vertex_capacity,
+            wgpu::BufferUsages::VERTEX,
+            bytemuck::cast_slice(&batch.vertices),
+        );
+        upload(
+            ctx.render_ctx,
+            &mut self.index_buffer,
+            &mut self.index_capacity,
+            wgpu::BufferUsages::INDEX,
+            bytemuck::cast_slice(&batch.indices),
+        );
+        upload(
+            ctx.render_ctx,
+            &mut self.instance_buffer,
+            &mut self.instance_capacity,
+            wgpu::BufferUsages::VERTEX,
+            bytemuck::cast_slice(&batch.instances),
+        );
+
+        let pipeline = self.pipelines.get(&format).unwrap();
+
+        ctx.render_pass.set_pipeline(pipeline);
+
+        ctx.render_pass
+            .set_bind_group(0, self.bind_group.as_ref().unwrap(), &[]);
+
+        ctx.render_pass
+            .set_vertex_buffer(0, self.vertex_buffer.as_ref().unwrap().slice(..));
+        ctx.render_pass
+            .set_vertex_buffer(1, self.instance_buffer.as_ref().unwrap().slice(..));
+        ctx.render_pass.set_index_buffer(
+            self.index_buffer.as_ref().unwrap().slice(..),
+            wgpu::IndexFormat::Uint32,
+        );
+
+        for (i, indices) in batch.draws.iter().enumerate() {
+            let instance = i as u32;
+
+            ctx.render_pass
+                .draw_indexed(indices.clone(), 0, instance..instance + 1);
+        }
     }
 }
 
+// draws the 3d pass over the sky
 #[derive(Default)]
 pub struct RenderNode {
     shader_module: Option<wgpu::ShaderModule>,
+    sky_buffer: Option<wgpu::Buffer>,
     bind_group_version: u64,
     bind_group: Option<wgpu::BindGroup>,
     pipeline_layout: Option<wgpu::PipelineLayout>,
@@ -111,9 +522,14 @@ impl RenderNode {
                             visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
                             count: None,
                         },
+                        uniform_layout_entry(3, wgpu::ShaderStages::FRAGMENT),
                     ],
                 });
 
+        if self.sky_buffer.is_none() {
+            self.sky_buffer = Some(uniform_buffer::<ColorUniform>(render_ctx));
+        }
+
         let (color, depth) = state.d3_buffer.color_depth(render_ctx);
 
         let color = color.create_view(&Default::default());
@@ -144,6 +560,10 @@ impl RenderNode {
                             binding: 2,
                             resource: wgpu::BindingResource::Sampler(&sampler),
                         },
+                        wgpu::BindGroupEntry {
+                            binding: 3,
+                            resource: self.sky_buffer.as_ref().unwrap().as_entire_binding(),
+                        },
                     ],
                 }),
         );
@@ -192,6 +612,7 @@ impl RenderNode {
                             visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
                             count: None,
                         },
+                        uniform_layout_entry(3, wgpu::ShaderStages::FRAGMENT),
                     ],
                 });
 
@@ -268,6 +689,322 @@ impl PassNode<GameState> for RenderNode {
             self.pipelines.insert(ctx.view.format, pipeline);
         }
 
+        ctx.render_ctx.queue.write_buffer(
+            self.sky_buffer.as_ref().unwrap(),
+            0,
+            bytemuck::bytes_of(&ColorUniform::new(state.daylight.sky)),
+        );
+
+        let pipeline = self.pipelines.get(&ctx.view.format).unwrap();
+
+        ctx.render_pass.set_pipeline(pipeline);
+
+        ctx.render_pass
+            .set_bind_group(0, self.bind_group.as_ref().unwrap(), &[]);
+
+        ctx.render_pass.draw(0..3, 0..1);
+    }
+}
+
+// multiplies everything drawn before it by the daylight shade, runs after the sprites
+#[derive(Default)]
+pub struct ShadeNode {
+    shader_module: Option<wgpu::ShaderModule>,
+    shade_buffer: Option<wgpu::Buffer>,
+    bind_group: Option<wgpu::BindGroup>,
+    pipeline_layout: Option<wgpu::PipelineLayout>,
+    pipelines: HashMap<wgpu::TextureFormat, wgpu::RenderPipeline>,
+}
+
+impl ShadeNode {
+    #[inline]
+    fn create_pipeline_layout(&mut self, render_ctx: &RenderCtx) {
+        self.shader_module = Some(
+            render_ctx
+                .device
+                .create_shader_module(&wgpu::include_wgsl!("shade.wgsl")),
+        );
+
+        let bind_group_layout =
+            render_ctx
+                .device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: None,
+                    entries: &[uniform_layout_entry(0, wgpu::ShaderStages::FRAGMENT)],
+                });
+
+        let shade_buffer = uniform_buffer::<ColorUniform>(render_ctx);
+
+        self.bind_group = Some(
+            render_ctx
+                .device
+                .create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: None,
+                    layout: &bind_group_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: shade_buffer.as_entire_binding(),
+                    }],
+                }),
+        );
+
+        self.shade_buffer = Some(shade_buffer);
+
+        self.pipeline_layout = Some(render_ctx.device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        ));
+    }
+
+    #[inline]
+    fn create_pipeline(
+        &mut self,
+        render_ctx: &RenderCtx,
+        format: wgpu::TextureFormat,
+        samples: u32,
+    ) -> wgpu::RenderPipeline {
+        // result = shade * destination, alpha is left alone
+        let blend = wgpu::BlendState {
+            color: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::Dst,
+                dst_factor: wgpu::BlendFactor::Zero,
+                operation: wgpu::BlendOperation::Add,
+            },
+            alpha: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::Zero,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+        };
+
+        render_ctx
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: None,
+                layout: Some(self.pipeline_layout.as_ref().unwrap()),
+                vertex: wgpu::VertexState {
+                    module: self.shader_module.as_ref().unwrap(),
+                    entry_point: "main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: self.shader_module.as_ref().unwrap(),
+                    entry_point: "main",
+                    targets: &[wgpu::ColorTargetState {
+                        format,
+                        blend: Some(blend),
+                        write_mask: wgpu::ColorWrites::COLOR,
+                    }],
+                }),
+                primitive: Default::default(),
+                multisample: wgpu::MultisampleState {
+                    count: samples,
+                    ..Default::default()
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: wgpu::TextureFormat::Depth24Plus,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::Always,
+                    stencil: Default::default(),
+                    bias: Default::default(),
+                }),
+            })
+    }
+}
+
+impl PassNode<GameState> for ShadeNode {
+    #[inline]
+    fn run<'a>(&'a mut self, ctx: &mut PassNodeCtx<'_, 'a>, state: &mut GameState) {
+        if self.pipeline_layout.is_none() {
+            self.create_pipeline_layout(ctx.render_ctx);
+        }
+
+        if !self.pipelines.contains_key(&ctx.view.format) {
+            let pipeline = self.create_pipeline(
+                ctx.render_ctx,
+                ctx.view.format,
+                ctx.data.get::<SampleCount>().unwrap().0,
+            );
+            self.pipelines.insert(ctx.view.format, pipeline);
+        }
+
+        ctx.render_ctx.queue.write_buffer(
+            self.shade_buffer.as_ref().unwrap(),
+            0,
+            bytemuck::bytes_of(&ColorUniform::new(state.daylight.shade)),
+        );
+
+        let pipeline = self.pipelines.get(&ctx.view.format).unwrap();
+
+        ctx.render_pass.set_pipeline(pipeline);
+
+        ctx.render_pass
+            .set_bind_group(0, self.bind_group.as_ref().unwrap(), &[]);
+
+        ctx.render_pass.draw(0..3, 0..1);
+    }
+}
+
+// draws the ui drawn by `UiPass` over everything, runs after the daylight shade
+#[derive(Default)]
+pub struct UiNode {
+    shader_module: Option<wgpu::ShaderModule>,
+    bind_group_version: u64,
+    bind_group_layout: Option<wgpu::BindGroupLayout>,
+    bind_group: Option<wgpu::BindGroup>,
+    pipeline_layout: Option<wgpu::PipelineLayout>,
+    pipelines: HashMap<wgpu::TextureFormat, wgpu::RenderPipeline>,
+}
+
+impl UiNode {
+    #[inline]
+    fn create_bind_group(&mut self, render_ctx: &RenderCtx, state: &mut GameState) {
+        let (color, _) = state.ui_buffer.color_depth(render_ctx);
+
+        let color = color.create_view(&Default::default());
+
+        // the ui buffer is the size of the window, so every pixel lands on one texel
+        let sampler = render_ctx.device.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        self.bind_group = Some(
+            render_ctx
+                .device
+                .create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: None,
+                    layout: self.bind_group_layout.as_ref().unwrap(),
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&color),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&sampler),
+                        },
+                    ],
+                }),
+        );
+    }
+
+    #[inline]
+    fn create_pipeline_layout(&mut self, render_ctx: &RenderCtx) {
+        self.shader_module = Some(
+            render_ctx
+                .device
+                .create_shader_module(&wgpu::include_wgsl!("ui.wgsl")),
+        );
+
+        let bind_group_layout =
+            render_ctx
+                .device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: None,
+                    entries: &[
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 0,
+                            ty: wgpu::BindingType::Texture {
+                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                                multisampled: false,
+                            },
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 1,
+                            ty: wgpu::BindingType::Sampler {
+                                filtering: true,
+                                comparison: false,
+                            },
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            count: None,
+                        },
+                    ],
+                });
+
+        self.pipeline_layout = Some(render_ctx.device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        ));
+
+        self.bind_group_layout = Some(bind_group_layout);
+    }
+
+    #[inline]
+    fn create_pipeline(
+        &mut self,
+        render_ctx: &RenderCtx,
+        format: wgpu::TextureFormat,
+        samples: u32,
+    ) -> wgpu::RenderPipeline {
+        render_ctx
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: None,
+                layout: Some(self.pipeline_layout.as_ref().unwrap()),
+                vertex: wgpu::VertexState {
+                    module: self.shader_module.as_ref().unwrap(),
+                    entry_point: "main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: self.shader_module.as_ref().unwrap(),
+                    entry_point: "main",
+                    targets: &[wgpu::ColorTargetState {
+                        format,
+                        // sprites blended onto the transparent ui buffer are premultiplied
+                        blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::COLOR,
+                    }],
+                }),
+                primitive: Default::default(),
+                multisample: wgpu::MultisampleState {
+                    count: samples,
+                    ..Default::default()
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: wgpu::TextureFormat::Depth24Plus,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::Always,
+                    stencil: Default::default(),
+                    bias: Default::default(),
+                }),
+            })
+    }
+}
+
+impl PassNode<GameState> for UiNode {
+    #[inline]
+    fn run<'a>(&'a mut self, ctx: &mut PassNodeCtx<'_, 'a>, state: &mut GameState) {
+        if self.pipeline_layout.is_none() {
+            self.create_pipeline_layout(ctx.render_ctx);
+        }
+
+        if self.bind_group.is_none() || self.bind_group_version != state.ui_buffer.version() {
+            self.bind_group_version = state.ui_buffer.version();
+
+            self.create_bind_group(ctx.render_ctx, state);
+        }
+
+        if !self.pipelines.contains_key(&ctx.view.format) {
+            let pipeline = self.create_pipeline(
+                ctx.render_ctx,
+                ctx.view.format,
+                ctx.data.get::<SampleCount>().unwrap().0,
+            );
+            self.pipelines.insert(ctx.view.format, pipeline);
+        }
+
         let pipeline = self.pipelines.get(&ctx.view.format).unwrap();
 
         ctx.render_pass.set_pipeline(pipeline);
@@ -0,0 +1,166 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use crate::{
+    banner::Patterns, config::Config, crop::Crops, input::Input, item_kind::ItemKinds,
+    save::Save, species::TreeSpecies, world::World,
+};
+
+// recordings are line based so they can be streamed to disk, the first line is
+// a save of the world when recording started and every line after it is the
+// input for a single step
+pub struct Recorder {
+    file: BufWriter<File>,
+}
+
+impl Recorder {
+    #[inline]
+    pub fn create(path: impl AsRef<Path>, start: &Save) -> ike::anyhow::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+
+        writeln!(file, "{}", ron::to_string(start)?)?;
+        file.flush()?;
+
+        Ok(Self { file })
+    }
+
+    // flushes every frame so a recording survives the game crashing
+    #[inline]
+    pub fn record(&mut self, input: &Input) -> ike::anyhow::Result<()> {
+        writeln!(self.file, "{}", ron::to_string(input)?)?;
+        self.file.flush()?;
+
+        Ok(())
+    }
+}
+
+pub struct Recording {
+    pub start: Save,
+    pub frames: Vec<Input>,
+}
+
+impl Recording {
+    #[inline]
+    pub fn read(path: impl AsRef<Path>) -> ike::anyhow::Result<Self> {
+        let source = fs::read_to_string(path)?;
+        let mut lines = source.lines().filter(|line| !line.trim().is_empty());
+
+        let start = match lines.next() {
+            Some(line) => Save::parse(line)?,
+            None => ike::anyhow::bail!("recording is empty"),
+        };
+
+        let frames = lines.map(ron::from_str).collect::<Result<_, _>>()?;
+
+        Ok(Self { start, frames })
+    }
+
+    // runs the whole recording without a window, the config and registries
+    // must match the ones used when recording for the result to match
+    #[inline]
+    pub fn simulate(
+        self,
+        cfg: &Config,
+        kinds: &ItemKinds,
+        crops: &Crops,
+        species: &TreeSpecies,
+        patterns: &Patterns,
+    ) -> World {
+        let mut world = self.start.into_world(species);
+
+        for input in &self.frames {
+            world.step(input, cfg, kinds, crops, species, patterns);
+        }
+
+        world
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ike::prelude::*;
+
+    use super::*;
+    use crate::{input::ButtonInput, inventory::HOTBAR_SIZE, save::tests::snapshot};
+
+    const PRESS: ButtonInput = ButtonInput {
+        down: true,
+        pressed: true,
+        released: false,
+    };
+
+    const RELEASE: ButtonInput = ButtonInput {
+        down: false,
+        pressed: false,
+        released: true,
+    };
+
+    // a short session placing the collector, tilling, dragging and selecting
+    // slots that don't exist, as an older or hand edited recording might
+    fn session() -> Vec<Input> {
+        let mut frames = Vec::new();
+
+        for i in 0..600 {
+            let t = i as f32 / 60.0;
+
+            let mut input = Input {
+                delta_time: 1.0 / 60.0,
+                mouse: Vec2::new(t.cos(), t.sin() * 0.5) * 40.0 * (t * 0.3).sin(),
+                ..Default::default()
+            };
+
+            match i % 97 {
+                0 => input.select = Some(i / 97 % (HOTBAR_SIZE + 2)),
+                10 => input.secondary = PRESS,
+                20 => input.secondary = RELEASE,
+                30 => input.primary = PRESS,
+                40 => input.primary = RELEASE,
+                50 => {
+                    input.hotbar = Some(HOTBAR_SIZE + 4);
+                    input.primary = PRESS;
+                }
+                _ => {}
+            }
+
+            frames.push(input);
+        }
+
+        frames
+    }
+
+    #[test]
+    fn replay_is_deterministic() {
+        let cfg = Config::default();
+        let kinds = ItemKinds::load_headless(&cfg.items.path).unwrap();
+        let crops = Crops::load_headless(&cfg.crops.path).unwrap();
+        let species = TreeSpecies::load(&cfg.trees.path).unwrap();
+        let patterns = Patterns::load(&cfg.banners.path).unwrap();
+
+        let path = std::env::temp_dir().join(format!("replay-{}.ron", std::process::id()));
+
+        let start = Save::from_world(&World::new(5), Vec2::ZERO);
+        let mut recorder = Recorder::create(&path, &start).unwrap();
+
+        for input in session() {
+            recorder.record(&input).unwrap();
+        }
+
+        drop(recorder);
+
+        let first = Recording::read(&path).unwrap();
+        let second = Recording::read(&path).unwrap();
+
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(first.frames.len(), 600);
+
+        let first = first.simulate(&cfg, &kinds, &crops, &species, &patterns);
+        let second = second.simulate(&cfg, &kinds, &crops, &species, &patterns);
+
+        assert_eq!(first.tick, 600);
+        assert_eq!(snapshot(&first), snapshot(&second));
+    }
+}
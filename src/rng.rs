@@ -0,0 +1,25 @@
+use rand::{rngs::StdRng, SeedableRng};
+
+// keys for the independent streams derived from the world seed, every system
+// gets its own stream so adding a roll to one doesn't shift the others
+pub const TILE: u64 = 0;
+
+#[inline]
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// derives a random stream from `seed`, the same seed and keys always give the same stream
+#[inline]
+pub fn stream(seed: u64, keys: &[u64]) -> StdRng {
+    let mut hash = splitmix64(seed);
+
+    for key in keys {
+        hash = splitmix64(hash ^ key);
+    }
+
+    StdRng::seed_from_u64(hash)
+}
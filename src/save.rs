@@ -0,0 +1,641 @@
+use std::{fs, path::Path};
+
+use ike::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    banner::Banner,
+    cloth::Cloth,
+    game_state::GameState,
+    inventory::Inventory,
+    item::{Item, ItemType, Items},
+    species::{Species, SpeciesDef, TreeSpecies},
+    tile::{FarmPlant, Structure, Tile},
+    tree::{Tree, TreeStage},
+    world::World,
+};
+
+// bump when the save format changes, and add a migration in `Save::read`
+pub const SAVE_VERSION: u32 = 3;
+
+// picks seeds for things saved before the world was seeded
+#[inline]
+fn random_seed() -> u64 {
+    rand::random()
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum StructureSave {
+    Pole {
+        time: f32,
+        #[serde(default)]
+        banner: Banner,
+    },
+    Collector,
+    Tree {
+        stage: TreeStage,
+        #[serde(default = "random_seed")]
+        seed: u64,
+        #[serde(default)]
+        fruit_timer: f32,
+        // every tree was an apple tree before species were added
+        #[serde(default = "default_species")]
+        species: String,
+    },
+    Prop {
+        item: ItemType,
+    },
+}
+
+#[inline]
+fn default_species() -> String {
+    String::from("apple")
+}
+
+impl From<&Structure> for StructureSave {
+    #[inline]
+    fn from(structure: &Structure) -> Self {
+        match structure {
+            Structure::Pole { time, banner, .. } => Self::Pole {
+                time: *time,
+                banner: banner.clone(),
+            },
+            Structure::Collector => Self::Collector,
+            Structure::Tree(tree) => Self::Tree {
+                stage: tree.stage.clone(),
+                seed: tree.seed,
+                fruit_timer: tree.fruit_timer,
+                species: tree.species.clone(),
+            },
+            Structure::Prop(item) => Self::Prop { item: item.clone() },
+        }
+    }
+}
+
+impl StructureSave {
+    #[inline]
+    pub fn into_structure(self, species: &TreeSpecies) -> Structure {
+        match self {
+            StructureSave::Pole { time, banner } => {
+                let mut cloth = Cloth::banner();
+                cloth.paint(&banner);
+
+                Structure::Pole {
+                    cloth,
+                    time,
+                    banner,
+                }
+            }
+            StructureSave::Collector => Structure::Collector,
+            StructureSave::Tree {
+                stage,
+                seed,
+                fruit_timer,
+                species: id,
+            } => {
+                let mut tree = match species.get(&id) {
+                    Some(species) => Tree::new(species, stage, seed),
+                    None => {
+                        log::warn!("unknown tree species '{}', growing an apple tree", id);
+
+                        let fallback = Species {
+                            id,
+                            def: SpeciesDef::default(),
+                        };
+
+                        Tree::new(&fallback, stage, seed)
+                    }
+                };
+
+                tree.fruit_timer = fruit_timer;
+
+                Structure::Tree(tree)
+            }
+            StructureSave::Prop { item } => Structure::Prop(item),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum TileSave {
+    Grass {
+        structure: Option<StructureSave>,
+        destruction: f32,
+    },
+    Farmed {
+        time: f32,
+        plant: Option<FarmPlant>,
+    },
+}
+
+impl From<&Tile> for TileSave {
+    #[inline]
+    fn from(tile: &Tile) -> Self {
+        match tile {
+            Tile::Grass {
+                structure,
+                destruction,
+            } => Self::Grass {
+                structure: structure.as_ref().map(Into::into),
+                destruction: *destruction,
+            },
+            Tile::Farmed { time, plant } => Self::Farmed {
+                time: *time,
+                plant: plant.clone(),
+            },
+        }
+    }
+}
+
+impl TileSave {
+    #[inline]
+    pub fn into_tile(self, species: &TreeSpecies) -> Tile {
+        match self {
+            TileSave::Grass {
+                structure,
+                destruction,
+            } => Tile::Grass {
+                structure: structure.map(|structure| structure.into_structure(species)),
+                destruction,
+            },
+            TileSave::Farmed { time, plant } => Tile::Farmed { time, plant },
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SaveHeader {
+    version: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Save {
+    pub version: u32,
+    pub time: f32,
+    #[serde(default = "random_seed")]
+    pub seed: u64,
+    #[serde(default)]
+    pub tick: u64,
+    pub camera: Vec2,
+    pub tiles: Vec<(IVec2, TileSave)>,
+    pub items: Vec<Item>,
+    #[serde(default)]
+    pub inventory: Inventory,
+}
+
+impl Save {
+    #[inline]
+    pub fn from_state(state: &GameState) -> Self {
+        Self::from_world(&state.world, state.main_camera.transform.translation)
+    }
+
+    #[inline]
+    pub fn from_world(world: &World, camera: Vec2) -> Self {
+        Self {
+            version: SAVE_VERSION,
+            time: world.time,
+            seed: world.seed,
+            tick: world.tick,
+            camera,
+            tiles: world
+                .tiles
+                .iter()
+                .map(|(position, tile)| (*position, tile.into()))
+                .collect(),
+            items: world.items.items.values().cloned().collect(),
+            inventory: world.inventory.clone(),
+        }
+    }
+
+    #[inline]
+    pub fn read(path: impl AsRef<Path>) -> ike::anyhow::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    #[inline]
+    pub fn parse(source: &str) -> ike::anyhow::Result<Self> {
+        let header: SaveHeader = ron::from_str(source)?;
+
+        match header.version {
+            1 => Ok(ron::from_str::<v1::Save>(source)?.migrate().migrate()),
+            2 => Ok(ron::from_str::<v2::Save>(source)?.migrate()),
+            SAVE_VERSION => Ok(ron::from_str(source)?),
+            version => ike::anyhow::bail!(
+                "unsupported save version {}, expected at most {}",
+                version,
+                SAVE_VERSION
+            ),
+        }
+    }
+
+    #[inline]
+    pub fn write(&self, path: impl AsRef<Path>) -> ike::anyhow::Result<()> {
+        let path = path.as_ref();
+        let save = ron::ser::to_string_pretty(self, Default::default())?;
+
+        // write to a temporary file first so a crash mid-write can't corrupt the save
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, save)?;
+        fs::rename(tmp, path)?;
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn apply(self, state: &mut GameState) {
+        state.main_camera.transform.translation = self.camera;
+        state.world = self.into_world(&state.species);
+    }
+
+    #[inline]
+    pub fn into_world(self, species: &TreeSpecies) -> World {
+        let mut world = World::new(self.seed);
+
+        world.time = self.time;
+        world.tick = self.tick;
+
+        world.tiles = self
+            .tiles
+            .into_iter()
+            .map(|(position, tile)| (position, tile.into_tile(species)))
+            .collect();
+
+        world.items = Items::default();
+        world.items.items = self
+            .items
+            .into_iter()
+            .map(|item| (Id::new(), item))
+            .collect();
+
+        world.inventory = self.inventory;
+        world.inventory.normalize();
+
+        world
+    }
+}
+
+// saves from before crops were data driven, wheat was the only crop
+mod v1 {
+    use ike::prelude::*;
+    use serde::Deserialize;
+
+    use crate::tile::FarmPlant;
+
+    use super::{v2::Item, StructureSave};
+
+    #[derive(Deserialize)]
+    pub enum Plant {
+        Wheat { growth: f32 },
+    }
+
+    #[derive(Deserialize)]
+    pub enum TileSave {
+        Grass {
+            structure: Option<StructureSave>,
+            destruction: f32,
+        },
+        Farmed {
+            time: f32,
+            plant: Option<Plant>,
+        },
+    }
+
+    #[derive(Deserialize)]
+    pub struct Save {
+        pub time: f32,
+        pub camera: Vec2,
+        pub tiles: Vec<(IVec2, TileSave)>,
+        pub items: Vec<Item>,
+    }
+
+    impl Save {
+        #[inline]
+        pub fn migrate(self) -> super::v2::Save {
+            let tiles = self
+                .tiles
+                .into_iter()
+                .map(|(position, tile)| {
+                    let tile = match tile {
+                        TileSave::Grass {
+                            structure,
+                            destruction,
+                        } => super::TileSave::Grass {
+                            structure,
+                            destruction,
+                        },
+                        TileSave::Farmed { time, plant } => super::TileSave::Farmed {
+                            time,
+                            plant: plant.map(|Plant::Wheat { growth }| FarmPlant {
+                                crop: String::from("wheat"),
+                                growth,
+                            }),
+                        },
+                    };
+
+                    (position, tile)
+                })
+                .collect();
+
+            super::v2::Save {
+                time: self.time,
+                camera: self.camera,
+                tiles,
+                items: self.items,
+            }
+        }
+    }
+}
+
+// saves from before items were data driven
+mod v2 {
+    use ike::prelude::*;
+    use serde::Deserialize;
+
+    use crate::item::{self, ItemType};
+
+    use super::TileSave;
+
+    #[derive(Deserialize)]
+    pub enum Kind {
+        WheatSeed,
+        Wheat,
+        Pole,
+        Wood,
+        Sapling,
+    }
+
+    impl Kind {
+        #[inline]
+        pub fn id(&self) -> ItemType {
+            ItemType::new(match self {
+                Self::WheatSeed => "wheat_seed",
+                Self::Wheat => "wheat",
+                Self::Pole => "pole",
+                Self::Wood => "wood",
+                Self::Sapling => "sapling",
+            })
+        }
+    }
+
+    #[derive(Deserialize)]
+    pub struct Item {
+        pub position: Vec3,
+        pub ty: Kind,
+        pub velocity: Vec3,
+        pub count: u32,
+    }
+
+    #[derive(Deserialize)]
+    pub struct Save {
+        pub time: f32,
+        pub camera: Vec2,
+        pub tiles: Vec<(IVec2, TileSave)>,
+        pub items: Vec<Item>,
+    }
+
+    impl Save {
+        #[inline]
+        pub fn migrate(self) -> super::Save {
+            super::Save {
+                version: super::SAVE_VERSION,
+                time: self.time,
+                seed: super::random_seed(),
+                tick: 0,
+                camera: self.camera,
+                tiles: self.tiles,
+                items: self
+                    .items
+                    .into_iter()
+                    .map(|old| item::Item {
+                        position: old.position,
+                        ty: old.ty.id(),
+                        velocity: old.velocity,
+                        count: old.count,
+                        dropped: false,
+                        rest: 0.0,
+                    })
+                    .collect(),
+                inventory: Default::default(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::{config::Config, inventory::Stack};
+
+    // saves from before crops were data driven, wheat was the only crop
+    const V1_SAVE: &str = r#"(
+        version: 1,
+        time: 12.5,
+        camera: (10.0, -4.0),
+        tiles: [
+            ((0, 0), Farmed(time: 3.0, plant: Some(Wheat(growth: 0.25)))),
+            ((1, 0), Grass(structure: Some(Pole(time: 1.0)), destruction: 0.0)),
+            ((0, 1), Grass(structure: Some(Tree(stage: Grown)), destruction: 0.5)),
+        ],
+        items: [
+            (position: (1.0, 2.0, 0.0), ty: WheatSeed, velocity: (0.0, 0.0, 0.0), count: 3),
+        ],
+    )"#;
+
+    // saves from before items were data driven, crops are already named
+    const V2_SAVE: &str = r#"(
+        version: 2,
+        time: 40.0,
+        camera: (0.0, 8.0),
+        tiles: [
+            ((0, 0), Farmed(time: 6.0, plant: Some((crop: "carrot", growth: 0.5)))),
+            ((-1, 0), Grass(structure: Some(Collector), destruction: 0.0)),
+        ],
+        items: [
+            (position: (0.0, 0.0, 4.0), ty: Sapling, velocity: (1.0, 0.0, 0.0), count: 1),
+            (position: (5.0, 5.0, 0.0), ty: Wood, velocity: (0.0, 0.0, 0.0), count: 7),
+        ],
+    )"#;
+
+    // the save of `world` with its unordered parts sorted so equal worlds compare equal
+    pub fn snapshot(world: &World) -> String {
+        let mut save = Save::from_world(world, Vec2::ZERO);
+
+        save.tiles.sort_by_key(|(position, _)| (position.x, position.y));
+        save.items.sort_by(|a, b| {
+            a.ty.cmp(&b.ty)
+                .then(a.position.x.total_cmp(&b.position.x))
+                .then(a.position.y.total_cmp(&b.position.y))
+                .then(a.position.z.total_cmp(&b.position.z))
+        });
+
+        ron::to_string(&save).unwrap()
+    }
+
+    fn grass(structure: Structure) -> Tile {
+        Tile::Grass {
+            structure: Some(structure),
+            destruction: 0.0,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let species = TreeSpecies::load(&Config::default().trees.path).unwrap();
+        let mut world = World::new(3);
+
+        world.time = 321.5;
+        world.tick = 77;
+
+        let banner = Banner {
+            pattern: String::from("stripes"),
+            rows: vec![String::from("###"), String::from("...")],
+            colors: [[59, 125, 216], [245, 196, 39]],
+        };
+
+        let mut tree = Tree::new(species.get("oak").unwrap(), TreeStage::Flowering(0.5), 9);
+        tree.fruit_timer = 1.5;
+
+        world.tiles.insert(
+            IVec2::new(2, 0),
+            Tile::Farmed {
+                time: 4.0,
+                plant: Some(FarmPlant {
+                    crop: String::from("wheat"),
+                    growth: 0.75,
+                }),
+            },
+        );
+        world.tiles.insert(
+            IVec2::new(-2, 0),
+            grass(Structure::Pole {
+                cloth: Cloth::banner(),
+                time: 2.0,
+                banner: banner.clone(),
+            }),
+        );
+        world.tiles.insert(IVec2::new(0, 2), grass(Structure::Collector));
+        world.tiles.insert(IVec2::new(0, -2), grass(Structure::Tree(tree)));
+        world
+            .tiles
+            .insert(IVec2::new(2, 2), grass(Structure::Prop(ItemType::new("stool"))));
+
+        world.items.drop_at(ItemType::new("wood"), Vec2::new(10.0, 5.0), 4);
+        world.items.drop_at(ItemType::new("apple"), Vec2::new(-3.0, 7.5), 1);
+
+        world.inventory.slots[2] = Some(Stack {
+            ty: ItemType::new("wheat_seed"),
+            count: 12,
+        });
+        world.inventory.selected = 2;
+        world.inventory.banner = banner;
+
+        let path = std::env::temp_dir().join(format!("save-{}.ron", std::process::id()));
+
+        Save::from_world(&world, Vec2::new(16.0, -8.0))
+            .write(&path)
+            .unwrap();
+
+        let save = Save::read(&path).unwrap();
+
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(save.version, SAVE_VERSION);
+        assert_eq!(save.camera, Vec2::new(16.0, -8.0));
+
+        let loaded = save.into_world(&species);
+
+        assert_eq!(snapshot(&loaded), snapshot(&world));
+    }
+
+    #[test]
+    fn migrates_v1() {
+        let save = Save::parse(V1_SAVE).unwrap();
+
+        assert_eq!(save.version, SAVE_VERSION);
+        assert_eq!(save.time, 12.5);
+        assert_eq!(save.camera, Vec2::new(10.0, -4.0));
+        assert_eq!(save.tick, 0);
+
+        match &save.tiles[0] {
+            (position, TileSave::Farmed { time, plant }) => {
+                assert_eq!(*position, IVec2::new(0, 0));
+                assert_eq!(*time, 3.0);
+
+                let plant = plant.as_ref().unwrap();
+                assert_eq!(plant.crop, "wheat");
+                assert_eq!(plant.growth, 0.25);
+            }
+            _ => panic!("farmland wasn't migrated"),
+        }
+
+        match &save.tiles[1].1 {
+            TileSave::Grass {
+                structure: Some(StructureSave::Pole { time, banner }),
+                ..
+            } => {
+                assert_eq!(*time, 1.0);
+                assert_eq!(*banner, Banner::default());
+            }
+            _ => panic!("pole wasn't migrated"),
+        }
+
+        match &save.tiles[2].1 {
+            TileSave::Grass {
+                structure: Some(StructureSave::Tree { stage, species, .. }),
+                destruction,
+            } => {
+                assert!(matches!(stage, TreeStage::Grown));
+                assert_eq!(species, "apple");
+                assert_eq!(*destruction, 0.5);
+            }
+            _ => panic!("tree wasn't migrated"),
+        }
+
+        assert_eq!(save.items.len(), 1);
+        assert_eq!(save.items[0].ty, ItemType::new("wheat_seed"));
+        assert_eq!(save.items[0].position, Vec3::new(1.0, 2.0, 0.0));
+        assert_eq!(save.items[0].count, 3);
+        assert!(save.inventory.slots.iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn migrates_v2() {
+        let save = Save::parse(V2_SAVE).unwrap();
+
+        assert_eq!(save.version, SAVE_VERSION);
+        assert_eq!(save.time, 40.0);
+        assert_eq!(save.camera, Vec2::new(0.0, 8.0));
+
+        match &save.tiles[0].1 {
+            TileSave::Farmed {
+                plant: Some(plant), ..
+            } => {
+                assert_eq!(plant.crop, "carrot");
+                assert_eq!(plant.growth, 0.5);
+            }
+            _ => panic!("farmland wasn't kept"),
+        }
+
+        assert!(matches!(
+            save.tiles[1],
+            (_, TileSave::Grass {
+                structure: Some(StructureSave::Collector),
+                ..
+            })
+        ));
+
+        let items: Vec<_> = save
+            .items
+            .iter()
+            .map(|item| (item.ty.0.as_str(), item.count, item.velocity, item.dropped))
+            .collect();
+
+        assert_eq!(
+            items,
+            [
+                ("sapling", 1, Vec3::new(1.0, 0.0, 0.0), false),
+                ("wood", 7, Vec3::ZERO, false),
+            ]
+        );
+    }
+}
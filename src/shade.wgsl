@@ -0,0 +1,20 @@
+[[stage(vertex)]]
+fn main([[builtin(vertex_index)]] index: u32) -> [[builtin(position)]] vec4<f32> {
+	let x = -1.0 + f32((index & 1u32) << 2u32);
+	let y = -1.0 + f32((index & 2u32) << 1u32);
+
+	return vec4<f32>(x, y, 0.0, 1.0);
+}
+
+[[block]]
+struct Shade {
+	color: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> shade: Shade;
+
+[[stage(fragment)]]
+fn main() -> [[location(0)]] vec4<f32> {
+	return shade.color;
+}
@@ -18,6 +18,8 @@ struct VertexOutput {
 [[block]]
 struct Uniforms {
 	view_proj: mat4x4<f32>;
+	// direction towards the sun or moon in xyz, and its strength in w
+	light: vec4<f32>;
 };
 
 [[group(0), binding(0)]] 
@@ -49,7 +51,7 @@ struct FragmentInput {
 
 [[stage(fragment)]]
 fn main(in: FragmentInput) -> [[location(0)]] vec4<f32> {
-	let light_dir = normalize(vec3<f32>(1.0, 2.0, 1.0));
+	let light_dir = normalize(uniforms.light.xyz);
 
 	var normal: vec3<f32>;
 
@@ -61,8 +63,8 @@ fn main(in: FragmentInput) -> [[location(0)]] vec4<f32> {
 
 	var diffuse: f32;
 
-	if (dot(light_dir, in.normal) > .0) {
-		diffuse = 1.0;
+	if (dot(light_dir, normal) > .0) {
+		diffuse = 0.7 + 0.3 * uniforms.light.w;
 	} else {
 		diffuse = 0.7;
 	}
@@ -0,0 +1,190 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use ike::prelude::*;
+use rand::{rngs::StdRng, Rng};
+use serde::Deserialize;
+
+use crate::{item::ItemType, tree_gen::Generator};
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SpeciesDef {
+    pub sapling: ItemType,
+    // trees without fruit never flower
+    #[serde(default)]
+    pub fruit: Option<ItemType>,
+    #[serde(default)]
+    pub generator: Generator,
+    // number of branches split off the ends of the previous level, one entry
+    // per level above the trunk, only used by the branching generator
+    #[serde(default)]
+    pub branches: Vec<u32>,
+    // downward tilt of the first branches
+    #[serde(default)]
+    pub spread: f32,
+    // range of downward tilt added at every later level
+    #[serde(default)]
+    pub bend: [f32; 2],
+    // largest random turn around the trunk at every later level
+    #[serde(default)]
+    pub twist: f32,
+    pub trunk_radius: f32,
+    pub radius_decay: f32,
+    pub branch_length: f32,
+    pub leaf_size: f32,
+    // size of the leaf sphere on top of the trunk, 0 for none
+    #[serde(default)]
+    pub crown_size: f32,
+    #[serde(default)]
+    pub crown_height: f32,
+    pub trunk_color: [u8; 3],
+    pub leaf_color: [u8; 3],
+    #[serde(default = "default_flower_color")]
+    pub flower_color: [u8; 3],
+    #[serde(default = "default_fruit_color")]
+    pub fruit_color: [u8; 3],
+}
+
+#[inline]
+fn default_flower_color() -> [u8; 3] {
+    [250, 214, 228]
+}
+
+#[inline]
+fn default_fruit_color() -> [u8; 3] {
+    [214, 46, 46]
+}
+
+#[inline]
+pub fn color([r, g, b]: [u8; 3]) -> Color {
+    Color8::srgb(r, g, b).into()
+}
+
+// the apple tree, used for trees from saves whose species no longer exists
+impl Default for SpeciesDef {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            sapling: ItemType::new("sapling"),
+            fruit: Some(ItemType::new("apple")),
+            generator: Generator::Branching,
+            branches: vec![3, 2, 2, 1],
+            spread: 0.1,
+            bend: [0.1, 0.4],
+            twist: 1.0,
+            trunk_radius: 4.0,
+            radius_decay: 0.8,
+            branch_length: 8.0,
+            leaf_size: 8.0,
+            crown_size: 16.0,
+            crown_height: 40.0,
+            trunk_color: [180, 105, 43],
+            leaf_color: [76, 193, 59],
+            flower_color: default_flower_color(),
+            fruit_color: default_fruit_color(),
+        }
+    }
+}
+
+pub struct Species {
+    pub id: String,
+    pub def: SpeciesDef,
+}
+
+impl Species {
+    #[inline]
+    pub fn load(id: String, path: &Path) -> ike::anyhow::Result<Self> {
+        let def: SpeciesDef = toml::from_str(&fs::read_to_string(path)?)?;
+
+        match def.generator {
+            Generator::Branching if def.branches.is_empty() => {
+                ike::anyhow::bail!("tree '{}' has no branches", id);
+            }
+            Generator::LSystem(ref system) if !system.axiom.contains('F') => {
+                ike::anyhow::bail!("tree '{}' axiom never grows a branch", id);
+            }
+            Generator::SpaceColonization(ref colonization)
+                if colonization.influence <= colonization.kill_distance =>
+            {
+                ike::anyhow::bail!(
+                    "tree '{}' influence must be larger than kill-distance",
+                    id
+                );
+            }
+            _ => {}
+        }
+
+        if def.bend[0] > def.bend[1] {
+            ike::anyhow::bail!(
+                "tree '{}' bend goes from {} down to {}",
+                id,
+                def.bend[0],
+                def.bend[1]
+            );
+        }
+
+        if def.twist < 0.0 || def.trunk_radius <= 0.0 || def.branch_length <= 0.0 {
+            ike::anyhow::bail!(
+                "tree '{}' twist, trunk-radius and branch-length must be positive",
+                id
+            );
+        }
+
+        Ok(Self { id, def })
+    }
+}
+
+// kept sorted by id so picking a random species only depends on the seed
+#[derive(Default)]
+pub struct TreeSpecies {
+    pub species: BTreeMap<String, Species>,
+}
+
+impl TreeSpecies {
+    // loads every `*.toml` file in `path`, the file name is used as the species id
+    #[inline]
+    pub fn load(path: impl AsRef<Path>) -> ike::anyhow::Result<Self> {
+        let mut species = Self::default();
+
+        for entry in fs::read_dir(path)? {
+            let path = entry?.path();
+
+            if path.extension().map_or(true, |ext| ext != "toml") {
+                continue;
+            }
+
+            let id = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(id) => id.to_string(),
+                None => continue,
+            };
+
+            let def = Species::load(id.clone(), &path)
+                .map_err(|err| err.context(format!("failed to load tree '{}'", id)))?;
+
+            species.species.insert(id, def);
+        }
+
+        Ok(species)
+    }
+
+    #[inline]
+    pub fn get(&self, id: &str) -> Option<&Species> {
+        self.species.get(id)
+    }
+
+    #[inline]
+    pub fn from_sapling(&self, sapling: &ItemType) -> Option<&Species> {
+        self.species
+            .values()
+            .find(|species| species.def.sapling == *sapling)
+    }
+
+    #[inline]
+    pub fn random(&self, rng: &mut StdRng) -> Option<&Species> {
+        if self.species.is_empty() {
+            return None;
+        }
+
+        self.species.values().nth(rng.gen_range(0..self.species.len()))
+    }
+}
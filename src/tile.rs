@@ -4,175 +4,247 @@ use std::{
 };
 
 use ike::prelude::*;
-use kira::{instance::InstanceSettings, Value};
-use rand::{Rng, SeedableRng};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
 
 use crate::{
     assets::Assets,
-    audio::Audio,
+    banner::Banner,
     cloth::Cloth,
     config::Config,
+    crop::{Crop, Crops},
     game_state::GameState,
+    input::Input,
+    inventory::Inventory,
     iso::from_iso,
     item::{ItemType, Items},
+    item_kind::{ItemKinds, UseAction},
+    prop::Props,
+    render::MeshBatch,
+    species::{Species, TreeSpecies},
     tree::{Tree, TreeStage},
+    world::WorldEvent,
 };
 
-#[derive(Debug)]
-pub enum FarmPlant {
-    Wheat { growth: f32 },
+// where the cloth hangs off a pole, relative to the tile in the 3d pass
+#[inline]
+pub fn cloth_offset() -> Vec3 {
+    Vec3::new(-5.0, 30.5, 0.0)
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FarmPlant {
+    pub crop: String,
+    pub growth: f32,
 }
 
 impl FarmPlant {
     #[inline]
-    pub fn texture<'a>(&self, assets: &'a mut Assets, cfg: &Config, p: u64) -> &'a mut Texture {
-        let mut rng = rand::rngs::StdRng::seed_from_u64(p);
-
-        match self {
-            FarmPlant::Wheat { growth, .. } => {
-                match *growth + rng.gen_range(0.0..cfg.plants.wheat.growth_variance) {
-                    x if x < 1.0 => &mut assets.wheat_0,
-                    x if x < 2.0 => &mut assets.wheat_1,
-                    x if x < 3.0 => &mut assets.wheat_2,
-                    _ => &mut assets.wheat_3,
-                }
-            }
+    pub fn new(crop: &Crop) -> Self {
+        Self {
+            crop: crop.id.clone(),
+            growth: 0.0,
         }
     }
 
     #[inline]
-    pub fn harvestable(&self) -> bool {
-        match self {
-            FarmPlant::Wheat { growth } => *growth > 3.0,
-        }
+    pub fn texture<'a>(&self, crops: &'a Crops, p: u64) -> Option<&'a Texture> {
+        let crop = crops.get(&self.crop)?;
+
+        let mut rng = StdRng::seed_from_u64(p);
+
+        let variance = if crop.def.growth_variance > 0.0 {
+            rng.gen_range(0.0..crop.def.growth_variance)
+        } else {
+            0.0
+        };
+
+        crop.stage_texture(self.growth + variance)
     }
 
     #[inline]
-    pub fn update(&mut self, ctx: &mut UpdateCtx, cfg: &Config) {
-        match self {
-            FarmPlant::Wheat { growth } => {
-                *growth += ctx.delta_time * (1.0 / cfg.plants.wheat.growth_time)
-            }
+    pub fn harvestable(&self, crops: &Crops) -> bool {
+        crops
+            .get(&self.crop)
+            .map_or(false, |crop| self.growth > (crop.stage_count() - 1) as f32)
+    }
+
+    #[inline]
+    pub fn update(&mut self, delta_time: f32, crops: &Crops) {
+        if let Some(crop) = crops.get(&self.crop) {
+            self.growth += delta_time * (1.0 / crop.def.growth_time);
         }
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StructureKind {
+    Pole,
+    Collector,
+    Tree,
+    Prop,
+}
+
 pub enum Structure {
-    Pole { cloth: Cloth, frames: u8, time: f32 },
+    Pole {
+        cloth: Cloth,
+        time: f32,
+        banner: Banner,
+    },
+    // pulls nearby items into the inventory
+    Collector,
     Tree(Tree),
+    // hand made model, drawn with the prop of the item that placed it
+    Prop(ItemType),
 }
 
 impl Structure {
+    // builds the structure placed by `item`, trees grow the species planted by the item
+    // and poles fly `banner`
     #[inline]
-    pub fn pole() -> Self {
-        let mut rng = rand::thread_rng();
+    pub fn from_kind(
+        kind: StructureKind,
+        item: &ItemType,
+        species: &TreeSpecies,
+        banner: &Banner,
+        rng: &mut StdRng,
+    ) -> Option<Self> {
+        match kind {
+            StructureKind::Pole => Some(Self::pole(banner.clone(), rng)),
+            StructureKind::Collector => Some(Self::Collector),
+            StructureKind::Tree => Some(Self::tree(species.from_sapling(item)?, rng)),
+            StructureKind::Prop => Some(Self::Prop(item.clone())),
+        }
+    }
+
+    #[inline]
+    pub fn pole(banner: Banner, rng: &mut StdRng) -> Self {
+        let mut cloth = Cloth::banner();
+        cloth.paint(&banner);
 
         Structure::Pole {
-            cloth: Cloth::generate(15, 4),
-            frames: 0,
+            cloth,
             time: rng.gen_range(0.0..std::f32::consts::PI),
+            banner,
         }
     }
 
     #[inline]
-    pub fn tree() -> Self {
-        let mut tree = Tree::default();
-        tree.trunk_color = Color8::srgb(180, 105, 43).into();
-        tree.leaf_color = Color8::srgb(76, 193, 59).into();
-        tree.trunk_radius = 4.0;
-        tree.radius_decay = 0.8;
-        tree.branch_length = 8.0;
-
-        tree.generate_mesh_sapling();
-
-        Structure::Tree(tree)
+    pub fn tree(species: &Species, rng: &mut StdRng) -> Self {
+        Structure::Tree(Tree::new(species, TreeStage::default(), rng.gen()))
     }
 
+    // `wind` is the wind at the structure, see `wind::sample`
     #[inline]
-    pub fn update(&mut self, ctx: &mut UpdateCtx, cfg: &Config) {
+    pub fn update(&mut self, delta_time: f32, wind: Vec3, cfg: &Config) {
         #[allow(unreachable_patterns)]
         match self {
-            Structure::Pole {
-                cloth,
-                frames,
-                time,
-            } => {
-                if cfg.graphics.instance_cloth {
-                    return;
-                }
-
-                *frames += 1;
-
-                *time += ctx.delta_time;
-
-                if *frames >= 2 {
-                    *frames = 0;
+            Structure::Pole { cloth, time, .. } => {
+                *time += delta_time;
 
-                    cloth.update(
-                        ctx.delta_time * 2.0,
-                        Vec3::new(-1.25, 0.0, -1.25),
-                        Vec2::new((*time * 10.0).cos() - 1.0, (*time * 10.0).sin() - 1.0),
-                    );
-                }
+                cloth.update(delta_time, wind, &cfg.cloth);
             }
-            Structure::Tree(tree) => tree.update(ctx),
+            Structure::Tree(tree) => tree.update(delta_time),
             _ => {}
         }
     }
 
     #[inline]
-    pub fn destroy(self, position: Vec2, _ctx: &mut UpdateCtx, items: &mut Items, _cfg: &Config) {
+    pub fn destroy(self, position: Vec2, items: &mut Items, rng: &mut StdRng, _cfg: &Config) {
         #[allow(unreachable_patterns)]
         match self {
             Self::Pole { .. } => {
-                items.spawn(ItemType::Pole, position, 1);
+                items.spawn(ItemType::new("pole"), position, 1, rng);
+            }
+            Self::Collector => {
+                items.spawn(ItemType::new("collector"), position, 1, rng);
+            }
+            Self::Prop(item) => {
+                items.spawn(item, position, 1, rng);
             }
             Self::Tree(tree) => {
-                if let TreeStage::Grown = tree.stage {
-                    items.spawn(ItemType::Wood, position + Vec2::new(-4.0, -2.0), 1);
-                    items.spawn(ItemType::Sapling, position + Vec2::new(4.0, 2.0), 1);
+                let sapling = tree.def.sapling;
+
+                if let TreeStage::Sapling(_) = tree.stage {
+                    items.spawn(sapling, position, 1, rng);
                 } else {
-                    items.spawn(ItemType::Sapling, position, 1);
+                    items.spawn(ItemType::new("wood"), position + Vec2::new(-4.0, -2.0), 1, rng);
+                    items.spawn(sapling, position + Vec2::new(4.0, 2.0), 1, rng);
                 }
             }
             _ => {}
         }
     }
 
+    // picks the fruit off fruiting trees, returns whether there was anything to pick
+    #[inline]
+    pub fn harvest(&mut self, position: Vec2, items: &mut Items, rng: &mut StdRng) -> bool {
+        match self {
+            Self::Tree(tree) if tree.harvestable() => {
+                let fruit = tree.def.fruit.clone();
+
+                for _ in 0..tree.harvest() {
+                    if let Some(ref fruit) = fruit {
+                        items.spawn(fruit.clone(), position, 1, rng);
+                    }
+                }
+
+                true
+            }
+            _ => false,
+        }
+    }
+
     #[inline]
     pub fn texture<'a>(&self, assets: &'a mut Assets) -> Option<&'a mut Texture> {
         match self {
             Self::Pole { .. } => Some(&mut assets.pole),
+            Self::Collector => Some(&mut assets.collector),
             _ => None,
         }
     }
 
+    // the mesh drawn for the structure, if it has one
+    #[inline]
+    pub fn mesh<'a>(&'a self, props: &'a Props) -> Option<&'a Mesh> {
+        match self {
+            Self::Pole { cloth, .. } => Some(&cloth.mesh),
+            Self::Tree(tree) => Some(&tree.mesh),
+            Self::Prop(item) => Some(&props.get(item)?.mesh),
+            Self::Collector => None,
+        }
+    }
+
     #[inline]
     pub fn mesh_render(
         &self,
-        ctx: &mut UpdateCtx,
+        meshes: &mut MeshBatch,
         position: Vec3,
         transform: &Transform3d,
-        instanced_cloth: &Cloth,
-        cfg: &Config,
+        props: &Props,
     ) {
         match self {
             Self::Pole { cloth, .. } => {
-                let transform = transform
-                    * Transform3d::from_translation(position + Vec3::new(-5.0, 30.5, 0.0));
+                let transform =
+                    transform * Transform3d::from_translation(position + cloth_offset());
 
-                if cfg.graphics.instance_cloth {
-                    ctx.draw(&instanced_cloth.mesh.render_3d(&transform));
-                } else {
-                    ctx.draw(&cloth.mesh.render_3d(&transform));
-                }
+                meshes.draw(&cloth.mesh, &transform);
             }
             Self::Tree(tree) => {
                 let transform =
                     transform * Transform3d::from_translation(position + Vec3::new(0.0, 0.0, 0.0));
 
-                ctx.draw(&tree.mesh.render_3d(&transform)); 
+                meshes.draw(&tree.mesh, &transform);
             }
+            Self::Prop(item) => {
+                if let Some(prop) = props.get(item) {
+                    let transform = transform * Transform3d::from_translation(position);
+
+                    meshes.draw(&prop.mesh, &transform);
+                }
+            }
+            Self::Collector => {}
         }
     }
 }
@@ -198,12 +270,12 @@ impl Tile {
     }
 
     #[inline]
-    pub fn grass() -> Self {
-        let mut rng = rand::thread_rng();
-
+    pub fn grass(rng: &mut StdRng, species: &TreeSpecies) -> Self {
         let structure = match rng.gen_range(0..100) {
-            0 => Some(Structure::pole()),
-            1..=5 => Some(Structure::tree()),
+            0 => Some(Structure::pole(Banner::default(), rng)),
+            1..=5 => species
+                .random(rng)
+                .map(|species| Structure::tree(species, rng)),
             _ => None,
         };
 
@@ -227,7 +299,7 @@ impl Tile {
         ctx: &mut UpdateCtx,
         tile_pos: Vec2,
         assets: &mut Assets,
-        cfg: &Config,
+        crops: &Crops,
     ) {
         match self {
             Self::Farmed {
@@ -251,7 +323,10 @@ impl Tile {
 
                         let p = hasher.finish();
 
-                        let texture = plant.texture(assets, cfg, p);
+                        let texture = match plant.texture(crops, p) {
+                            Some(texture) => texture,
+                            None => continue,
+                        };
 
                         let mut sprite = Sprite::new(
                             texture,
@@ -290,24 +365,17 @@ impl Tile {
     #[inline]
     pub fn render_mesh(
         &self,
-        ctx: &mut UpdateCtx,
+        meshes: &mut MeshBatch,
         position: Vec3,
         transform: &Transform3d,
-        instanced_cloth: &Cloth,
-        cfg: &Config,
+        props: &Props,
     ) {
         match self {
             Self::Grass {
                 structure: Some(structure),
                 ..
             } => {
-                structure.mesh_render(
-                    ctx,
-                    position,
-                    transform,
-                    instanced_cloth,
-                    cfg,
-                );
+                structure.mesh_render(meshes, position, transform, props);
             }
             _ => {}
         }
@@ -316,21 +384,27 @@ impl Tile {
     #[inline]
     pub fn hovered(
         &mut self,
-        ctx: &mut UpdateCtx,
+        input: &Input,
         cfg: &Config,
-        audio: &mut Audio,
         position: Vec2,
         items: &mut Items,
+        inventory: &mut Inventory,
+        kinds: &ItemKinds,
+        crops: &Crops,
+        species: &TreeSpecies,
+        rng: &mut StdRng,
+        events: &mut Vec<WorldEvent>,
     ) {
+        // the selected hotbar item is used on the tile
+        let action = inventory.selected_ty().map(|ty| kinds.use_action(ty));
+
         match self {
             Self::Grass { structure, .. } if structure.is_none() => {
-                if ctx.mouse_input.down(&cfg.controls.secondary) {
-                    match items.drag_ty() {
+                if input.secondary.down {
+                    match action {
                         None => {
-                            let mut rng = rand::thread_rng();
-
-                            if rng.gen_range(0..5) == 0 {
-                                items.spawn(ItemType::WheatSeed, position, 1);
+                            if rng.gen_range(0.0..1.0) < cfg.tile.grass_drop_chance {
+                                items.spawn(cfg.tile.grass_drop.clone(), position, 1, rng);
                             }
 
                             *self = Self::Farmed {
@@ -338,13 +412,17 @@ impl Tile {
                                 plant: None,
                             };
                         }
-                        Some(ItemType::Pole) => {
-                            items.consume();
-                            *structure = Some(Structure::pole());
-                        }
-                        Some(ItemType::Sapling) => {
-                            items.consume();
-                            *structure = Some(Structure::tree());
+                        Some(UseAction::PlaceStructure { structure: kind }) => {
+                            let item = inventory.selected_ty().unwrap();
+
+                            let banner = &inventory.banner;
+
+                            if let Some(placed) =
+                                Structure::from_kind(kind, item, species, banner, rng)
+                            {
+                                inventory.consume();
+                                *structure = Some(placed);
+                            }
                         }
                         _ => {}
                     }
@@ -354,43 +432,55 @@ impl Tile {
                 structure,
                 destruction,
             } => {
-                if ctx.mouse_input.pressed(&cfg.controls.secondary) {
-                    *destruction += 1.0;
-
-                    let mut settings = InstanceSettings::new();
+                // fruit is picked instead of hitting the tree
+                if input.secondary.pressed
+                    && structure.as_mut().unwrap().harvest(position, items, rng)
+                {
+                    return;
+                }
 
-                    settings.volume = Value::Fixed(0.3);
+                if input.secondary.pressed {
+                    *destruction += 1.0;
 
-                    audio.hit_arrangement.play(settings).unwrap();
+                    events.push(WorldEvent::Hit);
                 }
 
-                if *destruction > 3.0 && ctx.mouse_input.released(&cfg.controls.secondary) {
+                if *destruction > 3.0 && input.secondary.released {
                     *destruction = 0.0;
-                    structure.take().unwrap().destroy(position, ctx, items, cfg);
+                    structure.take().unwrap().destroy(position, items, rng, cfg);
                 }
             }
             Self::Farmed { plant, time, .. } => {
                 if let Some(farm_plant) = plant {
-                    if farm_plant.harvestable() && items.drag.is_none() {
-                        if ctx.mouse_input.down(&cfg.controls.secondary) {
+                    if farm_plant.harvestable(crops) && items.drag.is_none() {
+                        if input.secondary.down {
+                            let yields = match crops.get(&farm_plant.crop) {
+                                Some(crop) => crop.roll_yields(rng),
+                                None => Vec::new(),
+                            };
+
                             *time = cfg.tile.grass_growth_time;
                             *plant = None;
 
-                            let mut rng = rand::thread_rng();
+                            // spread drops out along the tile
+                            let n = yields.len();
 
-                            items.spawn(
-                                ItemType::WheatSeed,
-                                position + Vec2::new(-4.0, -2.0),
-                                1 + rng.gen_range(0..=8) / 8,
-                            );
-                            items.spawn(ItemType::Wheat, position + Vec2::new(4.0, 2.0), 1);
+                            for (i, (ty, count)) in yields.into_iter().enumerate() {
+                                let t = if n > 1 {
+                                    i as f32 / (n - 1) as f32 * 2.0 - 1.0
+                                } else {
+                                    0.0
+                                };
+
+                                items.spawn(ty, position + Vec2::new(4.0, 2.0) * t, count, rng);
+                            }
                         }
                     }
-                } else {
-                    if let Some(&ItemType::WheatSeed) = items.drag_ty() {
-                        if ctx.mouse_input.down(&cfg.controls.secondary) {
-                            *plant = Some(FarmPlant::Wheat { growth: 0.0 });
-                            items.consume();
+                } else if let Some(UseAction::PlantCrop) = action {
+                    if let Some(crop) = inventory.selected_ty().and_then(|ty| crops.from_seed(ty)) {
+                        if input.secondary.down {
+                            *plant = Some(FarmPlant::new(crop));
+                            inventory.consume();
                         }
                     }
                 }
@@ -400,26 +490,35 @@ impl Tile {
     }
 
     #[inline]
-    pub fn update(&mut self, ctx: &mut UpdateCtx, items: &mut Items, cfg: &Config) {
+    pub fn update(
+        &mut self,
+        delta_time: f32,
+        items: &mut Items,
+        crops: &Crops,
+        species: &TreeSpecies,
+        rng: &mut StdRng,
+        wind: Vec3,
+        cfg: &Config,
+    ) {
         match self {
             Self::Grass {
                 structure,
                 destruction,
             } => {
-                *destruction = (*destruction - ctx.delta_time).max(0.0);
+                *destruction = (*destruction - delta_time).max(0.0);
 
                 if let Some(s) = structure {
-                    s.update(ctx, cfg);
+                    s.update(delta_time, wind, cfg);
                 }
             }
             Self::Farmed { time, plant } => {
                 if let Some(plant) = plant {
-                    plant.update(ctx, cfg);
+                    plant.update(delta_time, crops);
                 } else {
-                    *time -= ctx.delta_time;
+                    *time -= delta_time;
 
                     if *time <= 0.0 {
-                        *self = Tile::grass();
+                        *self = Tile::grass(rng, species);
                     }
                 }
             }
@@ -1,10 +1,31 @@
 use ike::{d3::Indices, prelude::*};
-use rand::Rng;
-
-#[derive(Clone, Debug)]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    species::{color, Species, SpeciesDef},
+    tree_gen::Skeleton,
+};
+
+// time a sapling takes to grow up
+const GROWTH_TIME: f32 = 45.0;
+// size of a freshly planted sapling compared to a grown tree
+const SAPLING_AGE: f32 = 0.1;
+// growth between regenerating the mesh of a growing tree
+const REMESH_STEP: f32 = 0.01;
+// time a grown tree rests before flowering again
+const FRUIT_INTERVAL: f32 = 60.0;
+// time from the first flowers to ripe fruit
+const FLOWER_TIME: f32 = 30.0;
+// vertices around every branch ring
+pub const BRANCH_RES: usize = 5;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum TreeStage {
     Sapling(f32),
     Grown,
+    Flowering(f32),
+    // has ripe fruit that can be harvested
+    Fruiting,
 }
 
 impl Default for TreeStage {
@@ -18,164 +39,239 @@ impl Default for TreeStage {
 pub struct Tree {
     pub mesh: Mesh,
     pub stage: TreeStage,
-    pub trunk_radius: f32,
-    pub radius_decay: f32,
-    pub branch_length: f32,
-    pub trunk_color: Color,
-    pub leaf_color: Color,
+    // seeds the branch shapes, the same seed always grows the same tree
+    pub seed: u64,
+    pub species: String,
+    pub def: SpeciesDef,
+    pub skeleton: Skeleton,
+    // age the mesh was last generated at
+    pub meshed_age: f32,
+    // time spent grown since the last harvest
+    pub fruit_timer: f32,
+    // number of fruit on the tree, set when generating the mesh
+    pub fruit: u32,
 }
 
 impl Tree {
     #[inline]
-    pub fn update(&mut self, ctx: &mut UpdateCtx) {
+    pub fn new(species: &Species, stage: TreeStage, seed: u64) -> Self {
+        let mut tree = Self {
+            stage,
+            seed,
+            species: species.id.clone(),
+            def: species.def.clone(),
+            skeleton: Skeleton::generate(&species.def, seed),
+            ..Default::default()
+        };
+
+        tree.generate_mesh();
+
+        tree
+    }
+
+    // how grown the tree is, from just planted to 1 when fully grown
+    #[inline]
+    pub fn age(&self) -> f32 {
+        match self.stage {
+            TreeStage::Sapling(growth) => SAPLING_AGE + (1.0 - SAPLING_AGE) * growth.min(1.0),
+            _ => 1.0,
+        }
+    }
+
+    #[inline]
+    pub fn update(&mut self, delta_time: f32) {
         match self.stage {
             TreeStage::Sapling(ref mut growth) => {
-                *growth += ctx.delta_time / 45.0;
+                *growth += delta_time / GROWTH_TIME;
 
                 if *growth >= 1.0 {
                     self.stage = TreeStage::Grown;
-                    self.generate_mesh_grown();
+                    self.generate_mesh();
+                } else if self.age() - self.meshed_age >= REMESH_STEP {
+                    self.generate_mesh();
                 }
             }
-            _ => {}
-        }
-    }
-
-    #[inline]
-    pub fn generate_mesh_sapling(&mut self) {
-        self.mesh.vertices.clear();
-        self.mesh.indices.clear();
-
-        let base = Ring::new(self.trunk_color, self.trunk_radius / 2.0, 5);
-        let indices = base.insert(&mut self.mesh);
-
-        let mut branches: Vec<BranchBase> = vec![BranchBase {
-            indices,
-            position: Vec3::ZERO,
-            rotation: Vec3::ZERO,
-        }];
-        let mut trunk_radius = self.trunk_radius / 1.5;
-
-        let mut rng = rand::thread_rng();
-
-        for i in 0..4 {
-            for base in std::mem::replace(&mut branches, Vec::new()) {
-                let branch = Branch {
-                    position: base.position,
-                    rotation: base.rotation,
-                    res: 5,
-                    color: self.trunk_color,
-                    radius: trunk_radius,
-                    length: self.branch_length / 1.5,
-                };
-
-                let base = branch.generate(&mut self.mesh, &base.indices);
-
-                let num_branches: u32;
-
-                if i == 0 {
-                    num_branches = rng.gen_range(3..4);
-                } else if i < 3 {
-                    num_branches = rng.gen_range(2..3);
-                } else {
-                    num_branches = rng.gen_range(1..2);
+            TreeStage::Grown => {
+                if self.def.fruit.is_none() {
+                    return;
                 }
 
-                for j in 0..num_branches {
-                    let mut base = base.clone();
+                self.fruit_timer += delta_time;
 
-                    if i == 0 {
-                        let angle = (j as f32 / num_branches as f32) * std::f32::consts::TAU;
-                        base.rotation.y = angle;
-                        base.rotation.x -= 0.1;
-                    } else {
-                        base.rotation.y += rng.gen_range(-1.0..1.0);
-                        base.rotation.x -= rng.gen_range(0.1..0.4);
-                    }
-
-                    branches.push(base);
+                if self.fruit_timer >= FRUIT_INTERVAL {
+                    self.stage = TreeStage::Flowering(0.0);
+                    self.generate_mesh();
                 }
             }
+            TreeStage::Flowering(ref mut bloom) => {
+                *bloom += delta_time / FLOWER_TIME;
 
-            trunk_radius *= self.radius_decay;
+                if *bloom >= 1.0 {
+                    self.stage = TreeStage::Fruiting;
+                    self.generate_mesh();
+                }
+            }
+            TreeStage::Fruiting => {}
         }
+    }
+
+    #[inline]
+    pub fn harvestable(&self) -> bool {
+        matches!(self.stage, TreeStage::Fruiting)
+    }
 
-        for base in branches {
-            sphere(&mut self.mesh, base.position, 4.0, self.leaf_color);
+    // takes the fruit off the tree, returns how many there were
+    #[inline]
+    pub fn harvest(&mut self) -> u32 {
+        if !self.harvestable() {
+            return 0;
         }
 
-        self.mesh.calculate_normals();
+        let fruit = self.fruit;
+
+        self.stage = TreeStage::Grown;
+        self.fruit_timer = 0.0;
+        self.generate_mesh();
+
+        fruit
     }
 
+    // meshes the skeleton at the current age, levels grow out one after
+    // another while every branch thickens and the leaves swell
     #[inline]
-    pub fn generate_mesh_grown(&mut self) {
+    pub fn generate_mesh(&mut self) {
         self.mesh.vertices.clear();
         self.mesh.indices.clear();
 
-        let base = Ring::new(self.trunk_color, self.trunk_radius, 5);
-        let indices = base.insert(&mut self.mesh);
+        let age = self.age();
+        self.meshed_age = age;
 
-        let mut branches: Vec<BranchBase> = vec![BranchBase {
-            indices,
+        let depth = self.skeleton.depth;
+        let thickness = 0.3 + 0.7 * age;
+
+        // how far out the branches of a level have grown, overlapping with the next level
+        let level_growth = |level: usize| {
+            ((age * (depth + 1) as f32 - level as f32) / 2.0)
+                .max(0.0)
+                .min(1.0)
+        };
+
+        let trunk_color = color(self.def.trunk_color);
+        let leaf_color = color(self.def.leaf_color);
+
+        let base = Ring::new(trunk_color, self.def.trunk_radius * thickness, BRANCH_RES);
+        let root = BranchBase {
+            indices: base.insert(&mut self.mesh),
             position: Vec3::ZERO,
-            rotation: Vec3::ZERO,
-        }];
-        let mut trunk_radius = self.trunk_radius;
-
-        let mut rng = rand::thread_rng();
-
-        for i in 0..5 {
-            for base in std::mem::replace(&mut branches, Vec::new()) {
-                let branch = Branch {
-                    position: base.position,
-                    rotation: base.rotation,
-                    res: 5,
-                    color: self.trunk_color,
-                    radius: trunk_radius,
-                    length: self.branch_length,
-                };
-
-                let base = branch.generate(&mut self.mesh, &base.indices);
-
-                let num_branches: u32;
-
-                if i == 0 {
-                    num_branches = rng.gen_range(3..4);
-                } else if i < 3 {
-                    num_branches = rng.gen_range(2..3);
-                } else {
-                    num_branches = rng.gen_range(1..2);
-                }
+            rotation: Quat::IDENTITY,
+        };
+
+        // lowest level growing out of the end of every branch
+        let mut children: Vec<Option<usize>> = vec![None; self.skeleton.segments.len()];
 
-                for j in 0..num_branches {
-                    let mut base = base.clone();
+        for segment in &self.skeleton.segments {
+            if let Some(parent) = segment.parent {
+                let level = children[parent].map_or(segment.level, |l| l.min(segment.level));
+                children[parent] = Some(level);
+            }
+        }
 
-                    if i == 0 {
-                        let angle = (j as f32 / num_branches as f32) * std::f32::consts::TAU;
-                        base.rotation.y = angle;
-                        base.rotation.x -= 0.1;
-                    } else {
-                        base.rotation.y += rng.gen_range(-1.0..1.0);
-                        base.rotation.x -= rng.gen_range(0.1..0.4);
-                    }
+        // end of every branch that has started growing
+        let mut ends: Vec<Option<BranchBase>> = Vec::with_capacity(self.skeleton.segments.len());
 
-                    branches.push(base);
+        for (i, segment) in self.skeleton.segments.iter().enumerate() {
+            let growth = level_growth(segment.level);
+
+            let start = match segment.parent {
+                Some(parent) => ends[parent].as_ref(),
+                None => Some(&root),
+            };
+
+            let start = match start {
+                Some(start) if growth > 0.0 => start,
+                _ => {
+                    ends.push(None);
+                    continue;
                 }
+            };
+
+            let branch = Branch {
+                position: start.position,
+                rotation: segment.rotation,
+                res: BRANCH_RES,
+                color: trunk_color,
+                radius: self.def.trunk_radius
+                    * self.def.radius_decay.powi(segment.level as i32)
+                    * thickness,
+                length: self.def.branch_length * segment.length * growth,
+            };
+
+            let end = branch.generate(&mut self.mesh, &start.indices);
+
+            // leaves sit on the outermost grown branches and shrink away as
+            // new branches grow out of them
+            let next_growth = children[i].map_or(0.0, level_growth);
+
+            let leaf_size = self.def.leaf_size * (0.5 + 0.5 * age) * growth * (1.0 - next_growth);
+
+            if leaf_size > 0.5 {
+                sphere(&mut self.mesh, end.position, leaf_size, leaf_color);
             }
 
-            trunk_radius *= self.radius_decay;
+            ends.push(Some(end));
         }
 
-        for base in branches {
-            sphere(&mut self.mesh, base.position, 8.0, self.leaf_color);
+        let crown = self.def.crown_size * ((age - 0.5) * 2.0).max(0.0).min(1.0);
+
+        if crown > 0.5 {
+            sphere(
+                &mut self.mesh,
+                Vec3::new(0.0, self.def.crown_height * age, 0.0),
+                crown,
+                leaf_color,
+            );
         }
 
-        sphere(
-            &mut self.mesh,
-            Vec3::new(0.0, 40.0, 0.0),
-            16.0,
-            self.leaf_color,
-        );
+        // flowers and fruit grow on the outside of the leaves at some branch tips
+        self.fruit = 0;
+
+        let flower_color = color(self.def.flower_color);
+        let fruit_color = color(self.def.fruit_color);
+        let offset = self.def.leaf_size * 0.75;
+
+        for (segment, end) in self.skeleton.segments.iter().zip(&ends) {
+            let end = match end {
+                Some(end) if segment.fruit => end,
+                _ => continue,
+            };
+
+            let outward = Vec3::new(end.position.x, 0.0, end.position.z).normalize_or_zero();
+            let position = end.position + outward * offset - Vec3::Y * offset / 2.0;
+
+            match self.stage {
+                TreeStage::Flowering(_) => {
+                    sphere(&mut self.mesh, position, 1.5, flower_color);
+                }
+                TreeStage::Fruiting => {
+                    sphere(&mut self.mesh, position, 2.5, fruit_color);
+                    self.fruit += 1;
+                }
+                _ => {}
+            }
+        }
+
+        // always give something back for the wait
+        if let TreeStage::Fruiting = self.stage {
+            if self.fruit == 0 {
+                if let Some(Some(trunk)) = ends.first() {
+                    let position = trunk.position - Vec3::Y * self.def.leaf_size;
+
+                    sphere(&mut self.mesh, position, 2.5, fruit_color);
+                }
+
+                self.fruit = 1;
+            }
+        }
 
         self.mesh.calculate_normals();
     }
@@ -237,13 +333,13 @@ impl Ring {
 pub struct BranchBase {
     pub indices: Vec<usize>,
     pub position: Vec3,
-    pub rotation: Vec3,
+    pub rotation: Quat,
 }
 
 pub struct Branch {
     pub position: Vec3,
-    // euler angles
-    pub rotation: Vec3,
+    // turns the branch from pointing up
+    pub rotation: Quat,
     pub res: usize,
     pub color: Color,
     pub radius: f32,
@@ -253,10 +349,9 @@ pub struct Branch {
 impl Branch {
     pub fn generate(&self, mesh: &mut Mesh, base: &[usize]) -> BranchBase {
         let mut ring = Ring::new(self.color, self.radius, self.res);
-        let rot = euler_rot(self.rotation);
-        ring.rotate(rot);
+        ring.rotate(self.rotation);
 
-        let direction = rot * Vec3::Y;
+        let direction = self.rotation * Vec3::Y;
         let position = self.position + direction * self.length;
 
         ring.translate(position);
@@ -296,11 +391,15 @@ fn sphere(mesh: &mut Mesh, center: Vec3, radius: f32, color: Color) {
                 color,
             });
 
-            if i < 5 {
+            // the first and last rows meet in a point, so only one of the two
+            // triangles of a quad there has any area
+            if (1..5).contains(&i) {
                 mesh.indices.push(v + i0);
                 mesh.indices.push(v + j0);
                 mesh.indices.push(v + i1);
+            }
 
+            if i < 4 {
                 mesh.indices.push(v + i1);
                 mesh.indices.push(v + j0);
                 mesh.indices.push(v + j1);
@@ -309,15 +408,6 @@ fn sphere(mesh: &mut Mesh, center: Vec3, radius: f32, color: Color) {
     }
 }
 
-#[inline]
-fn euler_rot(euler: Vec3) -> Quat {
-    let m = Mat2::from_angle(euler.y);
-
-    let e = m * Vec2::new(euler.x, euler.z);
-
-    Quat::from_euler(EulerRot::XYZ, e.x, 0.0, e.y)
-}
-
 #[inline]
 fn bridge_loops(mesh: &mut Mesh, from: &[usize], to: &[usize]) {
     assert_eq!(from.len(), to.len());
@@ -0,0 +1,601 @@
+use std::collections::BTreeMap;
+
+use ike::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Deserialize;
+
+use crate::species::SpeciesDef;
+
+// longest string an l-system may rewrite to, later iterations are skipped
+const MAX_SYMBOLS: usize = 20_000;
+// rounds of growth towards the crown before a space colonization tree gives up
+const MAX_STEPS: usize = 200;
+// most segments a space colonization tree grows
+const MAX_NODES: usize = 800;
+
+// how the branches of a species are laid out
+#[derive(Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Generator {
+    // splits every tip into `branches` new branches per level
+    Branching,
+    LSystem(LSystem),
+    SpaceColonization(SpaceColonization),
+}
+
+impl Default for Generator {
+    #[inline]
+    fn default() -> Self {
+        Self::Branching
+    }
+}
+
+// a turtle walks the rewritten string, `F` grows a branch, `+`/`-` turn, `&`/`^`
+// pitch and `/`/`\` roll by `angle`, `[` and `]` start and end a side branch
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct LSystem {
+    pub axiom: String,
+    pub rules: BTreeMap<char, String>,
+    pub iterations: u32,
+    pub angle: f32,
+    // largest random change to every turn
+    #[serde(default)]
+    pub jitter: f32,
+    // length of side branches compared to the branch they grow from
+    #[serde(default = "default_length_decay")]
+    pub length_decay: f32,
+}
+
+#[inline]
+fn default_length_decay() -> f32 {
+    0.8
+}
+
+impl LSystem {
+    #[inline]
+    pub fn expand(&self) -> String {
+        let mut string = self.axiom.clone();
+
+        for _ in 0..self.iterations {
+            let next: String = string
+                .chars()
+                .map(|c| match self.rules.get(&c) {
+                    Some(rule) => rule.clone(),
+                    None => c.to_string(),
+                })
+                .collect();
+
+            if next.len() > MAX_SYMBOLS {
+                break;
+            }
+
+            string = next;
+        }
+
+        string
+    }
+}
+
+// branches grow towards points scattered in a sphere around the crown until
+// they reach them
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SpaceColonization {
+    pub attractors: u32,
+    pub crown_radius: f32,
+    // height of the crown center
+    pub crown_center: f32,
+    // how far a branch is pulled from
+    pub influence: f32,
+    // how close a branch gets before a point is reached
+    pub kill_distance: f32,
+}
+
+// a single branch, grown from the end of its parent
+#[derive(Clone)]
+pub struct Segment {
+    pub parent: Option<usize>,
+    // 0 for the trunk, levels grow out one after another
+    pub level: usize,
+    // turns the branch from pointing up
+    pub rotation: Quat,
+    // compared to the species branch length
+    pub length: f32,
+    // tips where flowers and fruit grow
+    pub fruit: bool,
+}
+
+// the shape of a tree at every age, parents always come before their children
+#[derive(Clone, Default)]
+pub struct Skeleton {
+    pub segments: Vec<Segment>,
+    // number of levels
+    pub depth: usize,
+}
+
+impl Skeleton {
+    #[inline]
+    pub fn generate(def: &SpeciesDef, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        let mut skeleton = match def.generator {
+            Generator::Branching => branching(def, &mut rng),
+            Generator::LSystem(ref system) => l_system(system, &mut rng),
+            Generator::SpaceColonization(ref colonization) => {
+                space_colonization(def, colonization, &mut rng)
+            }
+        };
+
+        skeleton.finish(&mut rng);
+        skeleton
+    }
+
+    // marks the fruiting tips and counts the levels
+    #[inline]
+    fn finish(&mut self, rng: &mut StdRng) {
+        let mut tip = vec![true; self.segments.len()];
+
+        for segment in &self.segments {
+            if let Some(parent) = segment.parent {
+                tip[parent] = false;
+            }
+        }
+
+        for (segment, tip) in self.segments.iter_mut().zip(tip) {
+            segment.fruit = tip && rng.gen_bool(0.35);
+        }
+
+        self.depth = self
+            .segments
+            .iter()
+            .map(|segment| segment.level + 1)
+            .max()
+            .unwrap_or(0);
+    }
+}
+
+#[inline]
+fn branching(def: &SpeciesDef, rng: &mut StdRng) -> Skeleton {
+    let mut segments = vec![Segment {
+        parent: None,
+        level: 0,
+        rotation: Quat::IDENTITY,
+        length: 1.0,
+        fruit: false,
+    }];
+    // euler angles of every segment
+    let mut angles = vec![Vec3::ZERO];
+    let mut tips = vec![0];
+
+    for (i, &num_branches) in def.branches.iter().enumerate() {
+        let num_branches = num_branches.max(1);
+
+        for parent in std::mem::replace(&mut tips, Vec::new()) {
+            for j in 0..num_branches {
+                let mut angle = angles[parent];
+
+                if i == 0 {
+                    angle.y = (j as f32 / num_branches as f32) * std::f32::consts::TAU;
+                    angle.x -= def.spread;
+                } else {
+                    if def.twist > 0.0 {
+                        angle.y += rng.gen_range(-def.twist..def.twist);
+                    }
+
+                    let [min, max] = def.bend;
+                    angle.x -= if min < max { rng.gen_range(min..max) } else { min };
+                }
+
+                tips.push(segments.len());
+                angles.push(angle);
+                segments.push(Segment {
+                    parent: Some(parent),
+                    level: i + 1,
+                    rotation: euler_rot(angle),
+                    length: 1.0,
+                    fruit: false,
+                });
+            }
+        }
+    }
+
+    Skeleton {
+        segments,
+        depth: 0,
+    }
+}
+
+#[derive(Clone)]
+struct Turtle {
+    parent: Option<usize>,
+    level: usize,
+    rotation: Quat,
+    length: f32,
+}
+
+#[inline]
+fn l_system(system: &LSystem, rng: &mut StdRng) -> Skeleton {
+    let mut segments = Vec::new();
+    let mut stack = Vec::new();
+
+    let mut turtle = Turtle {
+        parent: None,
+        level: 0,
+        rotation: Quat::IDENTITY,
+        length: 1.0,
+    };
+
+    for symbol in system.expand().chars() {
+        let mut turn = || {
+            if system.jitter > 0.0 {
+                system.angle + rng.gen_range(-system.jitter..system.jitter)
+            } else {
+                system.angle
+            }
+        };
+
+        match symbol {
+            'F' => {
+                segments.push(Segment {
+                    parent: turtle.parent,
+                    level: turtle.level,
+                    rotation: turtle.rotation,
+                    length: turtle.length,
+                    fruit: false,
+                });
+
+                turtle.parent = Some(segments.len() - 1);
+            }
+            '+' => turtle.rotation *= Quat::from_rotation_z(turn()),
+            '-' => turtle.rotation *= Quat::from_rotation_z(-turn()),
+            '&' => turtle.rotation *= Quat::from_rotation_x(turn()),
+            '^' => turtle.rotation *= Quat::from_rotation_x(-turn()),
+            '/' => turtle.rotation *= Quat::from_rotation_y(turn()),
+            '\\' => turtle.rotation *= Quat::from_rotation_y(-turn()),
+            '[' => {
+                stack.push(turtle.clone());
+                turtle.level += 1;
+                turtle.length *= system.length_decay;
+            }
+            ']' => {
+                if let Some(saved) = stack.pop() {
+                    turtle = saved;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Skeleton {
+        segments,
+        depth: 0,
+    }
+}
+
+#[inline]
+fn space_colonization(
+    def: &SpeciesDef,
+    colonization: &SpaceColonization,
+    rng: &mut StdRng,
+) -> Skeleton {
+    let center = Vec3::Y * colonization.crown_center;
+
+    let attractors: Vec<Vec3> = (0..colonization.attractors)
+        .map(|_| loop {
+            let point = Vec3::new(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+            );
+
+            if point.length_squared() <= 1.0 {
+                break center + point * colonization.crown_radius;
+            }
+        })
+        .collect();
+
+    colonize(def.branch_length, colonization, center, attractors)
+}
+
+// grows a trunk up from the origin towards `center` and branches out towards
+// `attractors` until they're all reached or nothing grows
+fn colonize(
+    branch_length: f32,
+    colonization: &SpaceColonization,
+    center: Vec3,
+    mut attractors: Vec<Vec3>,
+) -> Skeleton {
+    // node 0 is the base of the trunk, every other node ends a segment
+    let mut nodes = vec![Vec3::ZERO];
+    let mut parents = vec![0];
+
+    // grow the trunk straight up until it's pulled into the crown
+    while nodes.len() < MAX_NODES {
+        let top = nodes[nodes.len() - 1];
+
+        let pulled = attractors
+            .iter()
+            .any(|point| point.distance(top) < colonization.influence);
+
+        if pulled || top.y >= center.y {
+            break;
+        }
+
+        parents.push(nodes.len() - 1);
+        nodes.push(top + Vec3::Y * branch_length);
+    }
+
+    for _ in 0..MAX_STEPS {
+        // every point pulls on the closest node in range
+        let mut pull = vec![Vec3::ZERO; nodes.len()];
+
+        for point in &attractors {
+            let closest = nodes
+                .iter()
+                .enumerate()
+                .map(|(i, node)| (i, node.distance(*point)))
+                .filter(|&(_, distance)| distance < colonization.influence)
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+
+            if let Some((i, distance)) = closest {
+                if distance > 0.0 {
+                    pull[i] += (*point - nodes[i]) / distance;
+                }
+            }
+        }
+
+        let mut grown = false;
+
+        for (i, pull) in pull.into_iter().enumerate() {
+            let direction = pull.normalize_or_zero();
+
+            if direction == Vec3::ZERO || nodes.len() >= MAX_NODES {
+                continue;
+            }
+
+            let node = nodes[i] + direction * branch_length;
+
+            // points that stay closer to the node than to what it grew last step
+            // pull it the same way again, growing the same branch twice
+            let grown_before = (1..nodes.len()).any(|child| {
+                parents[child] == i && nodes[child].distance(node) < branch_length * 1e-3
+            });
+
+            if grown_before {
+                continue;
+            }
+
+            parents.push(i);
+            nodes.push(node);
+            grown = true;
+        }
+
+        attractors.retain(|point| {
+            nodes
+                .iter()
+                .all(|node| node.distance(*point) > colonization.kill_distance)
+        });
+
+        if !grown || attractors.is_empty() {
+            break;
+        }
+    }
+
+    let mut children = vec![0; nodes.len()];
+
+    for &parent in &parents[1..] {
+        children[parent] += 1;
+    }
+
+    // segment `i` ends at node `i + 1`, the level only goes up where the tree forks
+    let mut segments: Vec<Segment> = Vec::with_capacity(nodes.len() - 1);
+
+    for node in 1..nodes.len() {
+        let parent = parents[node];
+
+        let level = match parent {
+            0 => 0,
+            parent => segments[parent - 1].level + (children[parent] > 1) as usize,
+        };
+
+        let direction = (nodes[node] - nodes[parent]).normalize();
+
+        segments.push(Segment {
+            parent: parent.checked_sub(1),
+            level,
+            rotation: Quat::from_rotation_arc(Vec3::Y, direction),
+            length: 1.0,
+            fruit: false,
+        });
+    }
+
+    Skeleton {
+        segments,
+        depth: 0,
+    }
+}
+
+#[inline]
+fn euler_rot(euler: Vec3) -> Quat {
+    let m = Mat2::from_angle(euler.y);
+
+    let e = m * Vec2::new(euler.x, euler.z);
+
+    Quat::from_euler(EulerRot::XYZ, e.x, 0.0, e.y)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::{
+        species::TreeSpecies,
+        tree::{Tree, TreeStage, BRANCH_RES},
+    };
+
+    fn species() -> TreeSpecies {
+        TreeSpecies::load("assets/trees").unwrap()
+    }
+
+    fn stages() -> Vec<TreeStage> {
+        vec![
+            TreeStage::Sapling(0.0),
+            TreeStage::Sapling(0.5),
+            TreeStage::Grown,
+            TreeStage::Flowering(0.5),
+            TreeStage::Fruiting,
+        ]
+    }
+
+    #[test]
+    fn every_generator_has_a_species() {
+        let species = species();
+        let generators = |f: fn(&Generator) -> bool| {
+            species.species.values().any(|s| f(&s.def.generator))
+        };
+
+        assert!(generators(|g| matches!(g, Generator::Branching)));
+        assert!(generators(|g| matches!(g, Generator::LSystem(_))));
+        assert!(generators(|g| matches!(g, Generator::SpaceColonization(_))));
+    }
+
+    #[test]
+    fn parents_come_first() {
+        for species in species().species.values() {
+            for seed in 0..8 {
+                let skeleton = Skeleton::generate(&species.def, seed);
+
+                assert!(!skeleton.segments.is_empty(), "{} has no segments", species.id);
+
+                for (i, segment) in skeleton.segments.iter().enumerate() {
+                    if let Some(parent) = segment.parent {
+                        assert!(parent < i, "{} segment {} grows from {}", species.id, i, parent);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn meshes_are_valid() {
+        for species in species().species.values() {
+            for seed in 0..8 {
+                for stage in stages() {
+                    let tree = Tree::new(species, stage.clone(), seed);
+                    let mesh = &tree.mesh;
+                    let name = format!("{} {:?} seed {}", species.id, stage, seed);
+
+                    assert_eq!(mesh.indices.len() % 3, 0, "{}", name);
+
+                    let mut edges = HashSet::new();
+
+                    for triangle in mesh.indices.chunks(3) {
+                        for &index in triangle {
+                            assert!((index as usize) < mesh.vertices.len(), "{}", name);
+                        }
+
+                        let [a, b, c] = [triangle[0], triangle[1], triangle[2]]
+                            .map(|index| mesh.vertices[index as usize].position);
+                        let area = (b - a).cross(c - a).length() / 2.0;
+
+                        assert!(area > 1e-6, "{} has a degenerate triangle", name);
+
+                        for k in 0..3 {
+                            let (a, b) = (triangle[k], triangle[(k + 1) % 3]);
+                            edges.insert((a.min(b) as usize, a.max(b) as usize));
+                        }
+                    }
+
+                    // the base ring and the ring at the end of the trunk are the
+                    // first vertices, every side of both must be bridged
+                    for ring in 0..2 {
+                        let start = ring * BRANCH_RES;
+
+                        for i in 0..BRANCH_RES {
+                            let a = start + i;
+                            let b = start + (i + 1) % BRANCH_RES;
+
+                            assert!(
+                                edges.contains(&(a.min(b), a.max(b))),
+                                "{} trunk ring {} is open between {} and {}",
+                                name,
+                                ring,
+                                a,
+                                b
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn branches_grow_once() {
+        for species in species().species.values() {
+            if !matches!(species.def.generator, Generator::SpaceColonization(_)) {
+                continue;
+            }
+
+            for seed in 0..8 {
+                let skeleton = Skeleton::generate(&species.def, seed);
+
+                for (i, a) in skeleton.segments.iter().enumerate() {
+                    for (j, b) in skeleton.segments.iter().enumerate().skip(i + 1) {
+                        assert!(
+                            a.parent != b.parent || a.rotation.dot(b.rotation).abs() < 0.99999,
+                            "{} seed {} grows segments {} and {} the same way",
+                            species.id,
+                            seed,
+                            i,
+                            j
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn stuck_pull_grows_once() {
+        let colonization = SpaceColonization {
+            attractors: 2,
+            crown_radius: 10.0,
+            crown_center: 0.0,
+            influence: 18.0,
+            kill_distance: 2.0,
+        };
+
+        // pulled straight up by points either side, the branch it grows ends
+        // further from both than the base, so the base keeps being pulled
+        let side = Vec3::new(80f32.to_radians().sin(), 80f32.to_radians().cos(), 0.0) * 10.0;
+        let attractors = vec![side, side * Vec3::new(-1.0, 1.0, 1.0)];
+
+        let skeleton = colonize(6.0, &colonization, Vec3::ZERO, attractors);
+
+        assert_eq!(skeleton.segments.len(), 1);
+    }
+
+    #[test]
+    fn expand_is_capped() {
+        let mut rules = BTreeMap::new();
+        rules.insert('F', String::from("F[+F]F"));
+
+        let system = LSystem {
+            axiom: String::from("F"),
+            rules,
+            iterations: 64,
+            angle: 0.5,
+            jitter: 0.0,
+            length_decay: 0.8,
+        };
+
+        let expanded = system.expand();
+
+        assert!(expanded.len() <= MAX_SYMBOLS);
+        // stops at the last rewrite that fits rather than the axiom
+        assert!(expanded.len() * 6 > MAX_SYMBOLS);
+    }
+}
@@ -0,0 +1,65 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+// time between checking the disk for changes
+const POLL_INTERVAL: f32 = 0.5;
+
+// polls a file, or every file in a directory, for modifications
+pub struct Watcher {
+    pub path: PathBuf,
+    modified: Option<SystemTime>,
+    timer: f32,
+}
+
+impl Watcher {
+    #[inline]
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+
+        Self {
+            modified: last_modified(&path),
+            path,
+            timer: 0.0,
+        }
+    }
+
+    #[inline]
+    pub fn changed(&mut self, delta_time: f32) -> bool {
+        self.timer += delta_time;
+
+        if self.timer < POLL_INTERVAL {
+            return false;
+        }
+
+        self.timer = 0.0;
+
+        let modified = last_modified(&self.path);
+
+        if modified != self.modified {
+            self.modified = modified;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[inline]
+fn last_modified(path: &Path) -> Option<SystemTime> {
+    let metadata = fs::metadata(path).ok()?;
+    let mut modified = metadata.modified().ok()?;
+
+    // editing a file doesn't touch the directory, so check every entry
+    if metadata.is_dir() {
+        for entry in fs::read_dir(path).ok()?.flatten() {
+            if let Some(entry_modified) = entry.metadata().ok().and_then(|m| m.modified().ok()) {
+                modified = modified.max(entry_modified);
+            }
+        }
+    }
+
+    Some(modified)
+}
@@ -0,0 +1,21 @@
+use ike::prelude::*;
+
+use crate::config;
+
+// wind velocity at `position` in world space, every cloth samples the same field
+// so neighbouring flags sway together as gusts roll across the farm
+#[inline]
+pub fn sample(cfg: &config::Wind, time: f32, position: Vec2) -> Vec3 {
+    let [x, z] = cfg.direction;
+    let direction = Vec3::new(x, 0.0, z).normalize_or_zero();
+    let side = Vec3::new(-direction.z, 0.0, direction.x);
+
+    // gusts travel along the screen diagonal the wind blows along
+    let phase = (position.x - position.y) / cfg.gust_size.max(1.0);
+    let t = time * cfg.gust_frequency * std::f32::consts::TAU - phase;
+
+    let gust = (t.sin() + (t * 2.3 + 1.1).sin() * 0.5) / 1.5;
+    let sway = (t * 0.7 + 2.0).sin() * cfg.turbulence;
+
+    (direction * (1.0 + gust * cfg.gust_strength) + side * sway) * cfg.strength
+}
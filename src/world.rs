@@ -0,0 +1,588 @@
+use std::collections::HashMap;
+
+use ike::prelude::*;
+
+use crate::{
+    banner::{BannerChange, Patterns},
+    cloth::{Cloth, MESH_SCALE},
+    config::Config,
+    crop::Crops,
+    expansion,
+    input::Input,
+    inventory::{Inventory, HOTBAR_SIZE},
+    iso::{d3_tile_position, from_d3, from_iso, to_d3, to_iso},
+    item::Items,
+    item_kind::ItemKinds,
+    rng,
+    species::TreeSpecies,
+    tile::{cloth_offset, Structure, Tile},
+    wind,
+};
+
+// things that happened during a step that the game should react to, e.g. by playing sounds
+#[derive(Clone, Debug, PartialEq)]
+pub enum WorldEvent {
+    Hit,
+}
+
+// a cloth node held by the cursor
+#[derive(Clone, Copy, Debug)]
+pub struct ClothGrab {
+    pub tile: IVec2,
+    pub node: usize,
+}
+
+// the farm itself, has no knowledge of windows, rendering or audio
+pub struct World {
+    pub tiles: HashMap<IVec2, Tile>,
+    pub items: Items,
+    pub inventory: Inventory,
+    pub time: f32,
+    // every random roll in the world is derived from this
+    pub seed: u64,
+    // number of steps taken, keeps random rolls from repeating every step
+    pub tick: u64,
+    // purchasable tile under the mouse, and whether it's affordable
+    pub ghost_tile: Option<(IVec2, bool)>,
+    pub cloth_grab: Option<ClothGrab>,
+    // mouse position last step, cloth is pushed by how far it moved
+    pub prev_mouse: Vec2,
+}
+
+impl World {
+    #[inline]
+    pub fn new(seed: u64) -> Self {
+        let mut tiles = HashMap::new();
+
+        for x in -1..=1 {
+            for y in -1..=1 {
+                tiles.insert(IVec2::new(x, y), Tile::grass_plain());
+            }
+        }
+
+        Self {
+            tiles,
+            items: Items::default(),
+            inventory: Inventory::starting(),
+            time: 0.0,
+            seed,
+            tick: 0,
+            ghost_tile: None,
+            cloth_grab: None,
+            prev_mouse: Vec2::ZERO,
+        }
+    }
+
+    // bounding box of the tile centers in world space
+    #[inline]
+    pub fn bounds(&self) -> (Vec2, Vec2) {
+        let mut positions = self
+            .tiles
+            .keys()
+            .map(|position| from_iso(position.as_f32(), Vec2::splat(40.0)));
+
+        let first = match positions.next() {
+            Some(first) => first,
+            None => return (Vec2::ZERO, Vec2::ZERO),
+        };
+
+        positions.fold((first, first), |(min, max), position| {
+            (min.min(position), max.max(position))
+        })
+    }
+
+    #[inline]
+    pub fn step(
+        &mut self,
+        input: &Input,
+        cfg: &Config,
+        kinds: &ItemKinds,
+        crops: &Crops,
+        species: &TreeSpecies,
+        patterns: &Patterns,
+    ) -> Vec<WorldEvent> {
+        let mut events = Vec::new();
+
+        // advance time
+        self.time += input.delta_time;
+
+        self.update_hotbar(input, kinds);
+        self.update_banner(input, cfg, patterns);
+
+        let mouse = to_iso(input.mouse, Vec2::splat(40.0));
+
+        // update tiles
+        for (position, tile) in self.tiles.iter_mut() {
+            // each tile rolls from its own stream so iteration order doesn't matter
+            let mut rng = rng::stream(
+                self.seed,
+                &[rng::TILE, position.x as u64, position.y as u64, self.tick],
+            );
+
+            // if tile hovered
+            if mouse.x > position.x as f32 - 0.5
+                && mouse.x < position.x as f32 + 0.5
+                && mouse.y > position.y as f32 - 0.5
+                && mouse.y < position.y as f32 + 0.5
+            {
+                let position = from_iso(position.as_f32(), Vec2::splat(40.0));
+
+                tile.hovered(
+                    input,
+                    cfg,
+                    position,
+                    &mut self.items,
+                    &mut self.inventory,
+                    kinds,
+                    crops,
+                    species,
+                    &mut rng,
+                    &mut events,
+                );
+            }
+
+            let wind = wind::sample(
+                &cfg.wind,
+                self.time,
+                from_iso(position.as_f32(), Vec2::splat(40.0)),
+            );
+
+            tile.update(
+                input.delta_time,
+                &mut self.items,
+                crops,
+                species,
+                &mut rng,
+                wind,
+                cfg,
+            );
+        }
+
+        // buy new tiles with the selected wood
+        let ghost_position = mouse.round().as_i32();
+        self.ghost_tile = None;
+
+        if self.inventory.selected_ty() == Some(&cfg.expansion.item)
+            && expansion::can_expand(&self.tiles, ghost_position)
+        {
+            let cost = expansion::cost(&self.tiles, cfg);
+            let affordable = self.inventory.selected_count() >= cost;
+
+            if affordable && input.secondary.pressed {
+                self.inventory.consume_count(cost);
+                self.tiles.insert(ghost_position, Tile::grass_plain());
+            } else {
+                self.ghost_tile = Some((ghost_position, affordable));
+            }
+        }
+
+        self.items.update(input, &self.tiles, kinds, self.time, cfg);
+        self.collect_items(input, cfg, kinds);
+        self.update_cloth_cursor(input, cfg);
+
+        self.tick += 1;
+
+        events
+    }
+
+    // selecting slots and dragging stacks between the hotbar and the world,
+    // runs before `Items::update` which drops the world drag on release
+    #[inline]
+    fn update_hotbar(&mut self, input: &Input, kinds: &ItemKinds) {
+        // replays can be edited by hand or come from a larger hotbar
+        let select = input.select.filter(|&slot| slot < HOTBAR_SIZE);
+        let hotbar = input.hotbar.filter(|&slot| slot < HOTBAR_SIZE);
+
+        if let Some(slot) = select {
+            self.inventory.selected = slot;
+        }
+
+        if input.primary.pressed {
+            if let Some(slot) = hotbar {
+                self.inventory.selected = slot;
+
+                if self.inventory.slots[slot].is_some() {
+                    self.inventory.drag = Some(slot);
+                }
+            }
+        }
+
+        if !input.primary.released {
+            return;
+        }
+
+        if let Some(from) = self.inventory.drag.take() {
+            match hotbar {
+                Some(target) => {
+                    self.inventory.move_slot(from, target, kinds);
+                    self.inventory.selected = target;
+                }
+                None => {
+                    if let Some(stack) = self.inventory.slots[from].take() {
+                        self.items.drop_at(stack.ty, input.mouse, stack.count);
+                    }
+                }
+            }
+        } else if hotbar.is_some() {
+            // world items dropped on the hotbar are picked up
+            if let Some(mut item) = self.items.take_drag() {
+                item.count = self.inventory.insert(&item.ty, item.count, kinds);
+
+                if item.count > 0 {
+                    self.items.items.insert(Id::new(), item);
+                }
+            }
+        }
+    }
+
+    // picks the banner placed poles fly
+    #[inline]
+    fn update_banner(&mut self, input: &Input, cfg: &Config, patterns: &Patterns) {
+        let banner = &mut self.inventory.banner;
+
+        match input.banner {
+            Some(BannerChange::Pattern) => {
+                if let Some((id, def)) = patterns.next(&banner.pattern) {
+                    banner.pattern = id.clone();
+                    banner.rows = def.rows.clone();
+                }
+            }
+            Some(BannerChange::Color(i)) => {
+                let palette = &cfg.banners.palette;
+                let color = &mut banner.colors[i.min(1)];
+
+                let next = palette
+                    .iter()
+                    .position(|c| c == color)
+                    .map_or(0, |current| (current + 1) % palette.len());
+
+                if let Some(next) = palette.get(next) {
+                    *color = *next;
+                }
+            }
+            None => {}
+        }
+    }
+
+    // the cursor pushes cloth out of the way as it moves and grabs it with
+    // primary, runs after `Items::update` so items are picked up first
+    #[inline]
+    fn update_cloth_cursor(&mut self, input: &Input, cfg: &Config) {
+        let movement = input.mouse - self.prev_mouse;
+        self.prev_mouse = input.mouse;
+
+        if let Some(grab) = self.cloth_grab {
+            let origin = d3_tile_position(grab.tile, self.time) + cloth_offset();
+
+            match pole_cloth(&mut self.tiles, grab.tile) {
+                Some(cloth) if input.primary.down => {
+                    // keep the node under the cursor at its depth
+                    let position = origin + cloth.nodes[grab.node].position * MESH_SCALE;
+                    let target = to_d3(input.mouse, position);
+
+                    cloth.move_node(grab.node, (target - origin) / MESH_SCALE);
+
+                    return;
+                }
+                Some(cloth) => cloth.unpin(grab.node),
+                None => {}
+            }
+
+            self.cloth_grab = None;
+        }
+
+        let grab = input.primary.pressed
+            && input.hotbar.is_none()
+            && self.items.drag.is_none()
+            && self.inventory.drag.is_none();
+
+        // closest node in reach of the cursor, with its distance and tile
+        let mut closest: Option<(f32, IVec2, usize)> = None;
+
+        for (position, tile) in self.tiles.iter_mut() {
+            let cloth = match tile {
+                Tile::Grass {
+                    structure: Some(Structure::Pole { cloth, .. }),
+                    ..
+                } => cloth,
+                _ => continue,
+            };
+
+            let origin = d3_tile_position(*position, self.time) + cloth_offset();
+
+            for (i, node) in cloth.nodes.iter_mut().enumerate() {
+                let d3 = origin + node.position * MESH_SCALE;
+                let distance = from_d3(d3).distance(input.mouse);
+
+                if movement != Vec2::ZERO && !node.locked && distance < cfg.cloth.push_radius {
+                    let falloff = 1.0 - distance / cfg.cloth.push_radius;
+                    let push = to_d3(input.mouse, d3) - to_d3(input.mouse - movement, d3);
+
+                    node.position += push / MESH_SCALE * falloff * cfg.cloth.push_strength;
+                }
+
+                let closer = closest.map_or(true, |(closest, ..)| distance < closest);
+
+                // nodes on the pole stay on it
+                if grab && !node.locked && distance < cfg.cloth.grab_radius && closer {
+                    closest = Some((distance, *position, i));
+                }
+            }
+        }
+
+        if let Some((_, tile, node)) = closest {
+            if let Some(cloth) = pole_cloth(&mut self.tiles, tile) {
+                cloth.pin(node);
+
+                self.cloth_grab = Some(ClothGrab { tile, node });
+            }
+        }
+    }
+
+    // pulls items towards the cursor, when auto pickup is on, and placed collectors
+    #[inline]
+    fn collect_items(&mut self, input: &Input, cfg: &Config, kinds: &ItemKinds) {
+        let mut collectors: Vec<_> = self
+            .tiles
+            .iter()
+            .filter(|(_, tile)| {
+                matches!(
+                    tile,
+                    Tile::Grass {
+                        structure: Some(Structure::Collector),
+                        ..
+                    }
+                )
+            })
+            .map(|(position, _)| *position)
+            .collect();
+
+        // sorted so overlapping collectors don't depend on map order
+        collectors.sort_by_key(|position| (position.x, position.y));
+
+        let mut magnets: Vec<_> = collectors
+            .into_iter()
+            .map(|position| {
+                let target = from_iso(position.as_f32(), Vec2::splat(40.0));
+
+                (target, cfg.controls.collector_radius, false)
+            })
+            .collect();
+
+        if cfg.controls.auto_pickup {
+            magnets.push((input.mouse, cfg.controls.pickup_radius, true));
+        }
+
+        for (target, radius, cursor) in magnets {
+            let inventory = &self.inventory;
+
+            let collected = self.items.attract(
+                target,
+                radius,
+                cfg.controls.pickup_speed,
+                input.delta_time,
+                |item| !(cursor && item.dropped) && inventory.can_insert(&item.ty, kinds),
+            );
+
+            for mut item in collected {
+                item.count = self.inventory.insert(&item.ty, item.count, kinds);
+
+                if item.count > 0 {
+                    self.items.items.insert(Id::new(), item);
+                }
+            }
+        }
+    }
+}
+
+#[inline]
+fn pole_cloth(tiles: &mut HashMap<IVec2, Tile>, position: IVec2) -> Option<&mut Cloth> {
+    match tiles.get_mut(&position)? {
+        Tile::Grass {
+            structure: Some(Structure::Pole { cloth, .. }),
+            ..
+        } => Some(cloth),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{input::ButtonInput, inventory::Stack, item::ItemType, tile::FarmPlant};
+
+    const DELTA_TIME: f32 = 1.0 / 60.0;
+
+    // everything `World::step` reads, loaded from the game's own assets without a window
+    struct Registries {
+        cfg: Config,
+        kinds: ItemKinds,
+        crops: Crops,
+        species: TreeSpecies,
+        patterns: Patterns,
+    }
+
+    impl Registries {
+        fn load(cfg: Config) -> Self {
+            Self {
+                kinds: ItemKinds::load_headless(&cfg.items.path).unwrap(),
+                crops: Crops::load_headless(&cfg.crops.path).unwrap(),
+                species: TreeSpecies::load(&cfg.trees.path).unwrap(),
+                patterns: Patterns::load(&cfg.banners.path).unwrap(),
+                cfg,
+            }
+        }
+
+        fn step(&self, world: &mut World, input: &Input) -> Vec<WorldEvent> {
+            world.step(
+                input,
+                &self.cfg,
+                &self.kinds,
+                &self.crops,
+                &self.species,
+                &self.patterns,
+            )
+        }
+    }
+
+    fn tile_center(tile: IVec2) -> Vec2 {
+        from_iso(tile.as_f32(), Vec2::splat(40.0))
+    }
+
+    fn idle(mouse: Vec2) -> Input {
+        Input {
+            delta_time: DELTA_TIME,
+            mouse,
+            ..Default::default()
+        }
+    }
+
+    // secondary pressed over `tile`
+    fn use_on(tile: IVec2) -> Input {
+        Input {
+            secondary: ButtonInput {
+                down: true,
+                pressed: true,
+                released: false,
+            },
+            ..idle(tile_center(tile))
+        }
+    }
+
+    fn hold(world: &mut World, slot: usize, ty: &str, count: u32) {
+        world.inventory.slots[slot] = Some(Stack {
+            ty: ItemType::new(ty),
+            count,
+        });
+        world.inventory.selected = slot;
+    }
+
+    fn count(world: &World, ty: &str) -> u32 {
+        world
+            .inventory
+            .slots
+            .iter()
+            .flatten()
+            .filter(|stack| stack.ty.0 == ty)
+            .map(|stack| stack.count)
+            .sum()
+    }
+
+    #[test]
+    fn till_and_plant() {
+        let registries = Registries::load(Config::default());
+        let mut world = World::new(1);
+        let tile = IVec2::new(0, 0);
+
+        // nothing held tills the grass
+        world.inventory.selected = 1;
+        registries.step(&mut world, &use_on(tile));
+
+        assert!(matches!(world.tiles[&tile], Tile::Farmed { plant: None, .. }));
+
+        hold(&mut world, 1, "wheat_seed", 3);
+        registries.step(&mut world, &use_on(tile));
+
+        match &world.tiles[&tile] {
+            Tile::Farmed {
+                plant: Some(plant), ..
+            } => assert_eq!(plant.crop, "wheat"),
+            _ => panic!("wheat wasn't planted"),
+        }
+
+        assert_eq!(count(&world, "wheat_seed"), 2);
+    }
+
+    #[test]
+    fn harvest_drops_yields() {
+        let registries = Registries::load(Config::default());
+        let mut world = World::new(2);
+        let tile = IVec2::new(1, 0);
+
+        world.tiles.insert(
+            tile,
+            Tile::Farmed {
+                time: 0.0,
+                plant: Some(FarmPlant {
+                    crop: String::from("wheat"),
+                    growth: 100.0,
+                }),
+            },
+        );
+
+        world.inventory.selected = 1;
+        registries.step(&mut world, &use_on(tile));
+
+        assert!(matches!(world.tiles[&tile], Tile::Farmed { plant: None, .. }));
+        assert!(world
+            .items
+            .items
+            .values()
+            .any(|item| item.ty.0 == "wheat_seed"));
+    }
+
+    #[test]
+    fn expand_with_wood() {
+        let registries = Registries::load(Config::default());
+        let mut world = World::new(3);
+        let tile = IVec2::new(2, 0);
+
+        // the first tile costs `base-cost` wood
+        hold(&mut world, 1, "wood", 1);
+        registries.step(&mut world, &use_on(tile));
+
+        assert!(!world.tiles.contains_key(&tile));
+        assert_eq!(world.ghost_tile, Some((tile, false)));
+
+        hold(&mut world, 1, "wood", 10);
+        registries.step(&mut world, &use_on(tile));
+
+        assert!(world.tiles.contains_key(&tile));
+        assert_eq!(count(&world, "wood"), 10 - registries.cfg.expansion.base_cost);
+
+        // tiles must touch the island
+        registries.step(&mut world, &use_on(IVec2::new(5, 5)));
+
+        assert!(!world.tiles.contains_key(&IVec2::new(5, 5)));
+    }
+
+    #[test]
+    fn cursor_picks_up_items() {
+        let mut cfg = Config::default();
+        cfg.controls.auto_pickup = true;
+
+        let registries = Registries::load(cfg);
+        let mut world = World::new(4);
+        let mouse = tile_center(IVec2::new(1, 0));
+
+        let mut rng = rng::stream(world.seed, &[]);
+        world.items.spawn(ItemType::new("wheat"), mouse, 3, &mut rng);
+
+        for _ in 0..180 {
+            registries.step(&mut world, &idle(mouse));
+        }
+
+        assert!(world.items.items.is_empty());
+        assert_eq!(count(&world, "wheat"), 3);
+    }
+}